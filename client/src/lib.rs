@@ -0,0 +1,93 @@
+//! Safe wrapper around the C API exported by the BuildLimitChanger library, see
+//! `include/BuildLimitChanger.h` in the main crate for the raw signatures.
+use libloading::{Library, Symbol};
+use std::{ffi::{c_char, c_void, CString}, path::Path};
+
+pub use libloading::Error;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildLimit { pub min: i16, pub max: i16 }
+
+pub type RangeCallback = extern "C" fn(name: *const c_char, limit: BuildLimit, user_data: *mut c_void);
+
+type GetLimits = unsafe extern "C" fn(*const c_char, *mut BuildLimit) -> bool;
+type SetLimits = unsafe extern "C" fn(*const c_char, BuildLimit) -> bool;
+type NameOnly = unsafe extern "C" fn(*const c_char) -> bool;
+type NoArgs = extern "C" fn() -> bool;
+/// Shared by the functions that copy a string out like `snprintf`.
+type StringOut = unsafe extern "C" fn(*mut c_char, usize) -> usize;
+type RegisterCallback = extern "C" fn(Option<RangeCallback>, *mut c_void) -> bool;
+type UnregisterCallback = extern "C" fn(Option<RangeCallback>) -> bool;
+
+pub struct BuildLimitChanger { lib: Library }
+
+impl BuildLimitChanger {
+    /// Loads the library, or takes another reference to it if the game already loaded it.
+    ///
+    /// # Safety
+    /// Loading runs the library's constructors, which scan and hook the running game.
+    pub unsafe fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let lib = Library::new(path.as_ref().as_os_str())?;
+        // fail early on an old build that predates the API
+        lib.get::<GetLimits>(b"blc_get_limits\0")?;
+        Ok(Self { lib })
+    }
+
+    fn symbol<T>(&self, name: &[u8]) -> Result<Symbol<'_, T>, Error> { unsafe { self.lib.get(name) } }
+
+    /// Limits the game currently uses for `dimension`, if any are known.
+    pub fn limits(&self, dimension: &str) -> Result<Option<BuildLimit>, Error> {
+        let Ok(name) = CString::new(dimension) else { return Ok(None) };
+        let mut out = BuildLimit { min: 0, max: 0 };
+        Ok(unsafe { self.symbol::<GetLimits>(b"blc_get_limits\0")?(name.as_ptr(), &mut out) }.then_some(out))
+    }
+
+    /// Limits to apply the next time `dimension` loads, taking priority over the config file.
+    pub fn set_limits(&self, dimension: &str, limit: BuildLimit) -> Result<bool, Error> {
+        let Ok(name) = CString::new(dimension) else { return Ok(false) };
+        Ok(unsafe { self.symbol::<SetLimits>(b"blc_set_limits\0")?(name.as_ptr(), limit) })
+    }
+
+    pub fn clear_limits(&self, dimension: &str) -> Result<bool, Error> {
+        let Ok(name) = CString::new(dimension) else { return Ok(false) };
+        Ok(unsafe { self.symbol::<NameOnly>(b"blc_clear_limits\0")?(name.as_ptr()) })
+    }
+
+    pub fn is_hooked(&self) -> Result<bool, Error> { Ok(self.symbol::<NoArgs>(b"blc_is_hooked\0")?()) }
+
+    pub fn unhook(&self) -> Result<bool, Error> { Ok(self.symbol::<NoArgs>(b"blc_unhook\0")?()) }
+
+    /// The game version the mod detected, always `None` on Linux servers.
+    pub fn game_version(&self) -> Result<Option<String>, Error> {
+        let func = self.symbol::<StringOut>(b"blc_game_version\0")?;
+        let len = unsafe { func(std::ptr::null_mut(), 0) };
+        if len == 0 { return Ok(None); }
+        let mut buf = vec![0u8; len + 1];
+        unsafe { func(buf.as_mut_ptr() as *mut c_char, buf.len()) };
+        buf.truncate(len);
+        Ok(Some(String::from_utf8_lossy(&buf).into_owned()))
+    }
+
+    /// Writes a diagnostics bundle for bug reports and returns its path, `None` if that failed.
+    pub fn write_diagnostics(&self) -> Result<Option<String>, Error> {
+        let func = self.symbol::<StringOut>(b"blc_write_diagnostics\0")?;
+        let mut buf = vec![0u8; 4096];
+        let len = unsafe { func(buf.as_mut_ptr() as *mut c_char, buf.len()) };
+        if len == 0 { return Ok(None); }
+        buf.truncate(len.min(buf.len() - 1));
+        Ok(Some(String::from_utf8_lossy(&buf).into_owned()))
+    }
+
+    /// Registers a callback run after each range change on the thread loading the dimension.
+    ///
+    /// # Safety
+    /// `user_data` must stay valid and usable from that thread until the callback is unregistered.
+    pub unsafe fn register_callback(&self, callback: RangeCallback, user_data: *mut c_void) -> Result<bool, Error> {
+        Ok(self.symbol::<RegisterCallback>(b"blc_register_callback\0")?(Some(callback), user_data))
+    }
+
+    pub fn unregister_callback(&self, callback: RangeCallback) -> Result<bool, Error> {
+        Ok(self.symbol::<UnregisterCallback>(b"blc_unregister_callback\0")?(Some(callback)))
+    }
+}
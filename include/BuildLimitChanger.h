@@ -0,0 +1,63 @@
+#ifndef BUILD_LIMIT_CHANGER_H
+#define BUILD_LIMIT_CHANGER_H
+
+/* Generated with cbindgen from src/api.rs, regenerate with `cbindgen --config cbindgen.toml -o include/BuildLimitChanger.h` */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef struct BlcBuildLimit {
+  int16_t min;
+  int16_t max;
+} BlcBuildLimit;
+
+// Invoked after the hook writes a new range, with the dimension name, the applied limit and the registered user data.
+typedef void (*BlcRangeCallback)(const char *name, struct BlcBuildLimit limit, void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Writes the limit the game currently uses for `name` into `out`: the last applied range,
+// else a pending `blc_set_limits` value, else an absolute config entry. Returns false if none is known.
+bool blc_get_limits(const char *name, struct BlcBuildLimit *out);
+
+// Sets the limit used the next time `name` is loaded. Returns false for invalid input.
+bool blc_set_limits(const char *name, struct BlcBuildLimit limit);
+
+// Drops a limit set with `blc_set_limits`, so the config file applies again.
+bool blc_clear_limits(const char *name);
+
+bool blc_is_hooked(void);
+
+// Restores the original hooked function, returns false if the hook was not installed or could not be removed.
+bool blc_unhook(void);
+
+// Copies the detected game version into `buf` like `snprintf`, returning the full length
+// without the NUL terminator, or 0 when the version is unknown. `bedrock_server` on Linux
+// carries no version the mod can read, so there this always returns 0.
+size_t blc_game_version(char *buf, size_t len);
+
+// Writes a diagnostics bundle for bug reports into the config directory and copies its path
+// into `buf` like `blc_game_version`. Every call writes a new bundle, so pass a buffer big
+// enough for a path up front. Returns 0 if the bundle could not be written.
+size_t blc_write_diagnostics(char *buf, size_t len);
+
+// Registers `callback` to run after each range change, on the game thread that loads the dimension.
+bool blc_register_callback(BlcRangeCallback callback, void *user_data);
+
+// Removes every registration of `callback`, returns whether any was found.
+bool blc_unregister_callback(BlcRangeCallback callback);
+
+#if defined(__ANDROID__)
+void mod_init(void);
+#endif
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* BUILD_LIMIT_CHANGER_H */
@@ -0,0 +1,208 @@
+//! Starts `bedrock_server` with BuildLimitChanger in `LD_PRELOAD`, for Linux servers without an
+//! injector. The server shares the launcher's stdio, signals sent to the launcher are passed on,
+//! and the mod reports how its init went through a pipe named in `BLC_STATUS_FD`.
+use std::{env, path::PathBuf};
+
+const LIBRARY: &str = "libBuildLimitChanger.so";
+const USAGE: &str = "\
+Usage: blc-launcher [options] [--] [server arguments]
+
+Options:
+  --server <path>      bedrock_server to start, default ./bedrock_server
+  --mod <path>         libBuildLimitChanger.so, default next to the launcher or the server
+  --config-dir <dir>   passed to the mod as BLC_CONFIG_DIR
+  --log-dir <dir>      passed to the mod as BLC_LOG_DIR
+  -h, --help           show this help
+
+Other arguments, including --blc-* overrides, are passed to the server.";
+
+#[derive(Debug, Default, PartialEq)]
+struct Options {
+    server: Option<PathBuf>,
+    library: Option<PathBuf>,
+    env: Vec<(String, String)>,
+    args: Vec<String>,
+    help: bool,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| inline.clone().or_else(|| args.next()).ok_or(format!("{name} needs a value"));
+        match flag.as_str() {
+            "-h" | "--help" => options.help = true,
+            "--server" => options.server = Some(value("--server")?.into()),
+            "--mod" => options.library = Some(value("--mod")?.into()),
+            "--config-dir" => options.env.push((String::from("BLC_CONFIG_DIR"), value("--config-dir")?)),
+            "--log-dir" => options.env.push((String::from("BLC_LOG_DIR"), value("--log-dir")?)),
+            "--" => { options.args.extend(args.by_ref()); }
+            _ => options.args.push(arg),
+        }
+    }
+    Ok(options)
+}
+
+#[cfg(target_os = "linux")]
+mod launch {
+    use super::{Options, LIBRARY};
+    use std::{
+        env, ffi::OsString, fs::File, io::{self, BufRead, BufReader},
+        os::{fd::{FromRawFd, OwnedFd}, unix::process::ExitStatusExt},
+        path::{Path, PathBuf}, process::Command, sync::{Arc, Mutex},
+    };
+
+    /// Passed on to the server. Ctrl-C and friends already reach it through the process group.
+    const SIGNALS: [libc::c_int; 6] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP, libc::SIGQUIT, libc::SIGUSR1, libc::SIGUSR2];
+
+    fn find_library(explicit: Option<PathBuf>, server_dir: &Path) -> Result<PathBuf, String> {
+        let candidates = match explicit {
+            Some(path) => vec![path],
+            None => env::current_exe().ok().and_then(|e| Some(e.parent()?.join(LIBRARY))).into_iter()
+                .chain([server_dir.join(LIBRARY)]).collect(),
+        };
+        let path = candidates.iter().find(|p| p.is_file()).ok_or_else(|| format!("Cannot find {LIBRARY}, pass it with --mod"))?;
+        let path = path.canonicalize().map_err(|e| format!("Cannot resolve {}: {e}", path.display()))?;
+        // the loader splits LD_PRELOAD on both
+        if path.to_string_lossy().contains([' ', ':']) { return Err(format!("{} cannot be preloaded, its path has a space or colon", path.display())); }
+        Ok(path)
+    }
+
+    /// `value` in front of an existing `:` separated list.
+    fn prepend(var: &str, value: &Path) -> OsString {
+        let mut list = OsString::from(value);
+        if let Some(existing) = env::var_os(var).filter(|v| !v.is_empty()) {
+            list.push(":");
+            list.push(existing);
+        }
+        list
+    }
+
+    /// The read end stays in the launcher, the write end is inherited by the server.
+    fn status_pipe() -> io::Result<(File, OwnedFd)> {
+        let mut fds = [0; 2];
+        unsafe {
+            if libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) != 0 { return Err(io::Error::last_os_error()); }
+            let (read, write) = (File::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]));
+            if libc::fcntl(fds[1], libc::F_SETFD, 0) != 0 { return Err(io::Error::last_os_error()); }
+            Ok((read, write))
+        }
+    }
+
+    fn blocked_signals() -> libc::sigset_t {
+        unsafe {
+            let mut set: libc::sigset_t = std::mem::zeroed();
+            libc::sigemptyset(&mut set);
+            for signal in SIGNALS { libc::sigaddset(&mut set, signal); }
+            set
+        }
+    }
+
+    /// Waits for the blocked signals and sends them to the server. Signals from the kernel, like
+    /// Ctrl-C or a terminal hangup, went to the whole process group and already reached it.
+    fn forward_signals(set: libc::sigset_t, pid: libc::pid_t) {
+        std::thread::spawn(move || loop {
+            let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+            let signal = unsafe { libc::sigwaitinfo(&set, &mut info) };
+            if signal < 0 || info.si_code == libc::SI_KERNEL { continue; }
+            unsafe { libc::kill(pid, signal) };
+        });
+    }
+
+    fn print_status(line: &str) {
+        match line.split_once(' ').unwrap_or((line, "")) {
+            ("hooked", addr) => eprintln!("[blc-launcher] BuildLimitChanger hooked the game at {addr}"),
+            ("cancelled", _) => eprintln!("[blc-launcher] BuildLimitChanger init was cancelled by its startup timeout, the game runs unmodified"),
+            ("disabled", _) => eprintln!("[blc-launcher] BuildLimitChanger is disabled in its config, the game runs unmodified"),
+            ("failed", reason) => eprintln!("[blc-launcher] BuildLimitChanger failed: {reason}"),
+            _ => eprintln!("[blc-launcher] BuildLimitChanger: {line}"),
+        }
+    }
+
+    /// Runs the server to completion and returns the exit code to use for the launcher.
+    pub fn run(options: Options) -> Result<i32, String> {
+        let server = options.server.unwrap_or_else(|| PathBuf::from("./bedrock_server"));
+        let server = server.canonicalize().map_err(|e| format!("Cannot find {}: {e}", server.display()))?;
+        let server_dir = server.parent().unwrap_or(Path::new("/")).to_path_buf();
+        let library = find_library(options.library, &server_dir)?;
+        let (status_read, status_write) = status_pipe().map_err(|e| format!("Cannot create the status pipe: {e}"))?;
+
+        // blocked before any thread exists so they all inherit it, the server gets a clean mask
+        let signals = blocked_signals();
+        unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &signals, std::ptr::null_mut()) };
+
+        eprintln!("[blc-launcher] Starting {} with {}", server.display(), library.display());
+        let mut child = Command::new(&server)
+            .args(&options.args)
+            .current_dir(&server_dir)
+            .env("LD_PRELOAD", prepend("LD_PRELOAD", &library))
+            // bedrock_server expects its own libraries to be found next to it
+            .env("LD_LIBRARY_PATH", prepend("LD_LIBRARY_PATH", &server_dir))
+            .env("BLC_STATUS_FD", std::os::fd::AsRawFd::as_raw_fd(&status_write).to_string())
+            .envs(options.env)
+            .spawn()
+            .map_err(|e| format!("Cannot start {}: {e}", server.display()))?;
+        drop(status_write);
+        forward_signals(signals, child.id() as libc::pid_t);
+
+        let status = Arc::new(Mutex::new(None));
+        let reported = status.clone();
+        let reader = std::thread::spawn(move || {
+            for line in BufReader::new(status_read).lines().map_while(Result::ok) {
+                print_status(&line);
+                if let Ok(mut status) = reported.lock() { *status = Some(line); }
+            }
+        });
+
+        let exit = child.wait().map_err(|e| format!("Cannot wait for the server: {e}"))?;
+        // the pipe is at EOF once the server is gone, the reader may still have a line to print
+        reader.join().ok();
+        if status.lock().map(|s| s.is_none()).unwrap_or(true) {
+            eprintln!("[blc-launcher] BuildLimitChanger never reported back, check log.txt in its config folder");
+        }
+        Ok(exit.code().unwrap_or_else(|| 128 + exit.signal().unwrap_or(0)))
+    }
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) if !options.help => options,
+        Ok(_) => return println!("{USAGE}"),
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+    #[cfg(target_os = "linux")]
+    match launch::run(options) {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            eprintln!("[blc-launcher] {e}");
+            std::process::exit(1);
+        }
+    }
+    #[cfg(not(target_os = "linux"))] {
+        let _ = options;
+        eprintln!("blc-launcher only runs on Linux, inject the library with a DLL injector elsewhere");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn launcher_options_are_split_from_server_arguments() {
+        let args = ["--server=/srv/bedrock_server", "--config-dir", "/srv/blc", "--blc-overworld-max=512", "--", "--mod", "x"];
+        let options = parse_args(args.iter().map(|a| a.to_string())).unwrap();
+        assert_eq!(options.server, Some(PathBuf::from("/srv/bedrock_server")));
+        assert_eq!(options.library, None);
+        assert_eq!(options.env, [(String::from("BLC_CONFIG_DIR"), String::from("/srv/blc"))]);
+        assert_eq!(options.args, ["--blc-overworld-max=512", "--mod", "x"]);
+        assert!(parse_args(["--log-dir".to_string()].into_iter()).is_err());
+    }
+}
@@ -0,0 +1,128 @@
+//! C ABI for other native mods and launchers. Dimension names are NUL terminated UTF-8,
+//! limits set here are kept in memory and win over the config file until the process exits.
+use crate::{diagnostics, hook, utils};
+use std::{collections::HashMap, ffi::{c_char, c_void, CStr, CString}, sync::Mutex};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlcBuildLimit { pub min: i16, pub max: i16 }
+
+/// Invoked after the hook writes a new range, with the dimension name, the applied limit and the registered user data.
+pub type BlcRangeCallback = extern "C" fn(name: *const c_char, limit: BlcBuildLimit, user_data: *mut c_void);
+
+struct Callback { func: BlcRangeCallback, user_data: usize }
+
+static OVERRIDES: Mutex<Option<HashMap<String, BlcBuildLimit>>> = Mutex::new(None);
+static APPLIED: Mutex<Option<HashMap<String, BlcBuildLimit>>> = Mutex::new(None);
+static CALLBACKS: Mutex<Vec<Callback>> = Mutex::new(Vec::new());
+
+pub fn override_for(name: &str) -> Option<BlcBuildLimit> {
+    OVERRIDES.lock().ok()?.as_ref()?.get(name).copied()
+}
+
+/// Records the range the hook just wrote and tells every registered callback about it.
+pub fn notify_range_change(name: &str, limit: BlcBuildLimit) {
+    if let Ok(mut applied) = APPLIED.lock() { applied.get_or_insert_with(HashMap::new).insert(name.to_string(), limit); }
+    let Ok(c_name) = CString::new(name) else { return };
+    let callbacks: Vec<(BlcRangeCallback, usize)> = match CALLBACKS.lock() {
+        Ok(c) => c.iter().map(|c| (c.func, c.user_data)).collect(),
+        Err(_) => return,
+    };
+    for (func, user_data) in callbacks { func(c_name.as_ptr(), limit, user_data as *mut c_void); }
+}
+
+unsafe fn name_from_ptr<'a>(name: *const c_char) -> Option<&'a str> {
+    if name.is_null() { return None; }
+    CStr::from_ptr(name).to_str().ok()
+}
+
+/// Writes the limit the game currently uses for `name` into `out`: the last applied range,
+/// else a pending `blc_set_limits` value, else an absolute config entry. Returns false if none is known.
+#[no_mangle]
+pub unsafe extern "C" fn blc_get_limits(name: *const c_char, out: *mut BlcBuildLimit) -> bool {
+    let Some(name) = name_from_ptr(name) else { return false };
+    if out.is_null() { return false; }
+    let applied = APPLIED.lock().ok().and_then(|a| a.as_ref()?.get(name).copied());
+    let limit = applied
+        .or_else(|| override_for(name))
+        .or_else(|| {
+            let config = crate::config::load();
+            let (_, d) = config.limit_for(name)?;
+            Some(BlcBuildLimit { min: d.min.absolute()?, max: d.max.absolute()? })
+        });
+    match limit {
+        Some(limit) => { *out = limit; true }
+        None => false,
+    }
+}
+
+/// Sets the limit used the next time `name` is loaded. Returns false for invalid input.
+#[no_mangle]
+pub unsafe extern "C" fn blc_set_limits(name: *const c_char, limit: BlcBuildLimit) -> bool {
+    let Some(name) = name_from_ptr(name) else { return false };
+    if limit.min >= limit.max { return false; }
+    let Ok(mut overrides) = OVERRIDES.lock() else { return false };
+    log::info!("{} limits set through API: min {} max {}", name, limit.min, limit.max);
+    overrides.get_or_insert_with(HashMap::new).insert(name.to_string(), limit);
+    true
+}
+
+/// Drops a limit set with `blc_set_limits`, so the config file applies again.
+#[no_mangle]
+pub unsafe extern "C" fn blc_clear_limits(name: *const c_char) -> bool {
+    let Some(name) = name_from_ptr(name) else { return false };
+    OVERRIDES.lock().ok().and_then(|mut o| o.as_mut()?.remove(name)).is_some()
+}
+
+#[no_mangle]
+pub extern "C" fn blc_is_hooked() -> bool { hook::is_hooked() }
+
+/// Restores the original hooked function, returns false if the hook was not installed or could not be removed.
+#[no_mangle]
+pub extern "C" fn blc_unhook() -> bool { hook::uninstall_hook() }
+
+/// Copies the detected game version into `buf` like `snprintf`, returning the full length
+/// without the NUL terminator, or 0 when the version is unknown. `bedrock_server` on Linux
+/// carries no version the mod can read, so there this always returns 0.
+#[no_mangle]
+pub unsafe extern "C" fn blc_game_version(buf: *mut c_char, len: usize) -> usize {
+    let Some(version) = utils::game_version() else { return 0 };
+    copy_to_buf(version, buf, len)
+}
+
+unsafe fn copy_to_buf(text: &str, buf: *mut c_char, len: usize) -> usize {
+    if !buf.is_null() && len > 0 {
+        let copied = text.len().min(len - 1);
+        std::ptr::copy_nonoverlapping(text.as_ptr() as *const c_char, buf, copied);
+        *buf.add(copied) = 0;
+    }
+    text.len()
+}
+
+/// Writes a diagnostics bundle for bug reports into the config directory and copies its path
+/// into `buf` like `blc_game_version`. Every call writes a new bundle, so pass a buffer big
+/// enough for a path up front. Returns 0 if the bundle could not be written.
+#[no_mangle]
+pub unsafe extern "C" fn blc_write_diagnostics(buf: *mut c_char, len: usize) -> usize {
+    match diagnostics::write_bundle("requested through API") {
+        Ok(path) => copy_to_buf(&path.to_string_lossy(), buf, len),
+        Err(e) => { log::warn!("Cannot write diagnostics: {e}"); 0 }
+    }
+}
+
+/// Registers `callback` to run after each range change, on the game thread that loads the dimension.
+#[no_mangle]
+pub extern "C" fn blc_register_callback(callback: Option<BlcRangeCallback>, user_data: *mut c_void) -> bool {
+    let (Some(func), Ok(mut callbacks)) = (callback, CALLBACKS.lock()) else { return false };
+    callbacks.push(Callback { func, user_data: user_data as usize });
+    true
+}
+
+/// Removes every registration of `callback`, returns whether any was found.
+#[no_mangle]
+pub extern "C" fn blc_unregister_callback(callback: Option<BlcRangeCallback>) -> bool {
+    let (Some(func), Ok(mut callbacks)) = (callback, CALLBACKS.lock()) else { return false };
+    let before = callbacks.len();
+    callbacks.retain(|c| c.func as usize != func as usize);
+    callbacks.len() != before
+}
@@ -1,64 +1,904 @@
-use crate::utils::is_dir_writable;
-use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fs, path::{Path, PathBuf}, sync::OnceLock};
+use crate::utils::{self, glob_match, is_dir_writable};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{collections::HashMap, fmt, fs::{self, File, OpenOptions}, io::{self, Write}, path::{Path, PathBuf}, str::FromStr, sync::{Mutex, OnceLock}};
 
 #[derive(Serialize, Deserialize, Debug)]
-pub struct BuildLimit { pub min: i16, pub max: i16 }
+pub struct BuildLimit {
+    pub min: Height,
+    pub max: Height,
+    #[serde(default, skip_serializing_if = "Alignment::is_default")]
+    pub alignment: Alignment,
+}
+
+/// A config bound: numbers are absolute, signed strings such as `"+128"` are added to the
+/// value the game itself wrote, so configs survive changes to the vanilla defaults.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Height { Absolute(i16), Relative(i16) }
+
+impl Height {
+    pub fn resolve(self, original: i16) -> i16 {
+        match self { Height::Absolute(v) => v, Height::Relative(delta) => original.saturating_add(delta) }
+    }
+
+    pub fn absolute(self) -> Option<i16> {
+        match self { Height::Absolute(v) => Some(v), Height::Relative(_) => None }
+    }
+
+    /// Environment and command line syntax, where a plain `-128` has to be absolute: `~N`
+    /// (e.g. `~-64`) and `+N` are relative, every other number is absolute.
+    pub fn from_override(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        let (relative, digits) = match s.strip_prefix('~') { Some(rest) => (true, rest), None => (s.starts_with('+'), s) };
+        let value = digits.parse::<i16>().map_err(|e| format!("invalid height {s:?}: {e}"))?;
+        Ok(if relative { Height::Relative(value) } else { Height::Absolute(value) })
+    }
+}
+
+impl fmt::Display for Height {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self { Height::Absolute(v) => write!(f, "{v}"), Height::Relative(delta) => write!(f, "{delta:+}") }
+    }
+}
+
+impl FromStr for Height {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let value = s.parse::<i16>().map_err(|e| format!("invalid height {s:?}: {e}"))?;
+        Ok(if s.starts_with(['+', '-']) { Height::Relative(value) } else { Height::Absolute(value) })
+    }
+}
+
+impl Serialize for Height {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Height::Absolute(v) => serializer.serialize_i16(*v),
+            Height::Relative(_) => serializer.collect_str(self),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Height {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw { Number(i16), Text(String) }
+        match Raw::deserialize(deserializer)? {
+            Raw::Number(v) => Ok(Height::Absolute(v)),
+            Raw::Text(text) => text.parse().map_err(de::Error::custom),
+        }
+    }
+}
+
+/// How a range that is not on the 16 block sub chunk grid gets fixed up.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Alignment {
+    /// Min down, max up: the world is never smaller than configured.
+    #[default]
+    Expand,
+    /// Min up, max down: the world is never taller than configured.
+    Shrink,
+    /// Each bound to its closest multiple of 16.
+    Nearest,
+    /// Unaligned values are rejected and the game's range is kept.
+    Strict,
+}
+
+impl Alignment {
+    fn is_default(&self) -> bool { *self == Alignment::default() }
+
+    pub fn align(self, min: i16, max: i16) -> Result<(i16, i16), &'static str> {
+        // done in i32 so rounding i16::MAX up cannot overflow, then clamped back onto the grid
+        let down = |v: i16| { let v = v as i32; v - v.rem_euclid(16) };
+        let up = |v: i16| { let d = down(v); if d == v as i32 { d } else { d + 16 } };
+        let nearest = |v: i16| if (v as i32).rem_euclid(16) >= 8 { up(v) } else { down(v) };
+        let (new_min, new_max) = match self {
+            Alignment::Expand => (down(min), up(max)),
+            Alignment::Shrink => (up(min), down(max)),
+            Alignment::Nearest => (nearest(min), nearest(max)),
+            Alignment::Strict if min % 16 != 0 || max % 16 != 0 => return Err("values are not multiples of 16"),
+            Alignment::Strict => (min as i32, max as i32),
+        };
+        let clamp = |v: i32| v.clamp(i16::MIN as i32, i16::MAX as i32 - 15) as i16;
+        let (new_min, new_max) = (clamp(new_min), clamp(new_max));
+        if new_min >= new_max { return Err("aligned min is not below aligned max"); }
+        Ok((new_min, new_max))
+    }
+}
 
 type BuildLimitMap = HashMap<String, BuildLimit>;
 
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ModuleLocator { #[default] Auto, Dl, Maps }
+
+/// How long the game is held for init. Only `Wait` guarantees the hook is in before the first
+/// dimension is built, with the others a world saved with extended limits can open with vanilla
+/// ones. On Linux the scan always runs in the background and the server waits at `main`,
+/// elsewhere `Wait` scans on the loading thread.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeoutPolicy {
+    /// After `init_timeout_ms` let the game start and hook when the scan is done.
+    Continue,
+    /// After `init_timeout_ms` let the game start without the hook.
+    Cancel,
+    /// Hold the game until hooked.
+    #[default]
+    Wait,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct Settings {
+    /// Setting this to false while the game runs removes the hook on the next dimension load.
+    pub enabled: bool,
+    pub module_locator: ModuleLocator,
+    /// Library name or path holding the game code, or `"main"` for the main executable.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub module: Option<String>,
+    /// Writes a diagnostics bundle for bug reports into the config directory after every start.
+    pub diagnostics: bool,
+    /// How long the game waits for the background scan with `Continue` and `Cancel`.
+    pub init_timeout_ms: u64,
+    pub on_init_timeout: TimeoutPolicy,
+    /// How long the scan waits for the game module to be loaded, for mods loaded before it.
+    pub module_wait_ms: u64,
+    /// Logs the build limit and mob cap changes without making them.
+    pub dry_run: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self { Self { enabled: true, module_locator: ModuleLocator::default(), module: None, diagnostics: false, init_timeout_ms: 5000, on_init_timeout: TimeoutPolicy::default(), module_wait_ms: 60000, dry_run: false } }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Config {
+    /// Editor hint only, points at the JSON Schema written next to the config.
+    #[serde(rename = "$schema", default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+    #[serde(default)]
+    pub settings: Settings,
+    /// Built-in profile applied underneath the dimensions listed in the file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    /// Mob cap values by the names the scan logs for them, `water` being the one it anchors on.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub mob_caps: HashMap<String, f32>,
+    #[serde(flatten)]
+    pub dimensions: BuildLimitMap,
+    /// Wildcard and `re:` keys in the order `limit_for` tries them, with the compiled regex
+    /// for `re:` keys. Filled by `compile_patterns`.
+    #[serde(skip)]
+    patterns: Vec<(String, Option<regex_lite::Regex>)>,
+}
+
+pub struct Preset { pub name: &'static str, pub description: &'static str, pub dimensions: &'static [(&'static str, i16, i16)] }
+
+pub const PRESETS: &[Preset] = &[
+    Preset { name: "vanilla", description: "The game's own height ranges", dimensions: &[("Overworld", -64, 320), ("Nether", 0, 128), ("TheEnd", 0, 256)] },
+    Preset { name: "tall", description: "Overworld build limit raised to 2032", dimensions: &[("Overworld", -64, 2032), ("Nether", 0, 128), ("TheEnd", 0, 256)] },
+    Preset { name: "deep_nether", description: "Nether extended 256 blocks down and 128 up", dimensions: &[("Overworld", -64, 320), ("Nether", -256, 256), ("TheEnd", 0, 256)] },
+    Preset { name: "flat_void", description: "Every dimension from 0 to 256, the Overworld without its deepslate layer below 0", dimensions: &[("Overworld", 0, 256), ("Nether", 0, 256), ("TheEnd", 0, 256)] },
+];
+
+pub fn preset(name: &str) -> Option<&'static Preset> { PRESETS.iter().find(|p| p.name == name) }
+
+impl Preset {
+    fn limits(&self) -> impl Iterator<Item = (String, BuildLimit)> {
+        self.dimensions.iter().map(|&(name, min, max)| {
+            (name.to_string(), BuildLimit { min: Height::Absolute(min), max: Height::Absolute(max), alignment: Alignment::default() })
+        })
+    }
+
+    /// The preset spelled out as a complete config, for users who want to start editing from it.
+    pub fn to_config(&self) -> Config {
+        Config { schema: Some(format!("../{SCHEMA_FILE}")), settings: Settings::default(), preset: None, mob_caps: HashMap::new(), dimensions: self.limits().collect(), patterns: Vec::new() }
+    }
+}
+
+impl Config {
+    /// Fills in the dimensions of the referenced preset that the file does not list itself.
+    pub fn apply_preset(&mut self) {
+        let Some(name) = self.preset.as_deref() else { return };
+        let Some(preset) = preset(name) else {
+            let known: Vec<String> = PRESETS.iter().map(|p| format!("{} ({})", p.name, p.description)).collect();
+            return log::warn!("Unknown preset {:?}, known presets: {}", name, known.join(", "));
+        };
+        for (dimension, limit) in preset.limits() { self.dimensions.entry(dimension).or_insert(limit); }
+    }
+}
+
+/// Mob caps above this are rejected, vanilla ones are far below.
+pub const MAX_MOB_CAP: f32 = 1024.0;
+
+/// Config keys with this prefix are matched as a regex against the whole dimension name.
+const REGEX_PREFIX: &str = "re:";
+
+/// Compiled `re:` keys, the config is reloaded on every dimension load. `None` for invalid ones,
+/// which are only warned about once.
+static REGEXES: Mutex<Option<HashMap<String, Option<regex_lite::Regex>>>> = Mutex::new(None);
+
+fn compile_regex(key: &str, pattern: &str) -> Option<regex_lite::Regex> {
+    let mut cache = REGEXES.lock().unwrap();
+    cache.get_or_insert_with(HashMap::new).entry(key.to_string()).or_insert_with(|| {
+        regex_lite::Regex::new(&format!("^(?:{pattern})$"))
+            .map_err(|e| log::warn!("Invalid regex in config key {key}: {e}"))
+            .ok()
+    }).clone()
+}
+
+impl Config {
+    /// Orders the wildcard (`*`, `?`) and `re:` keys for `limit_for`: wildcards before regexes,
+    /// longer patterns first within each kind, and compiles the regexes.
+    pub fn compile_patterns(&mut self) {
+        let mut keys: Vec<&String> = self.dimensions.keys()
+            .filter(|key| key.starts_with(REGEX_PREFIX) || key.contains(['*', '?']))
+            .collect();
+        keys.sort_by(|a, b| a.starts_with(REGEX_PREFIX).cmp(&b.starts_with(REGEX_PREFIX))
+            .then(b.len().cmp(&a.len()))
+            .then(a.cmp(b)));
+        self.patterns = keys.into_iter().filter_map(|key| match key.strip_prefix(REGEX_PREFIX) {
+            Some(pattern) => Some((key.clone(), Some(compile_regex(key, pattern)?))),
+            None => Some((key.clone(), None)),
+        }).collect();
+    }
+
+    /// Looks up the entry for a dimension: an exact key first, then the patterns in the order
+    /// `compile_patterns` put them in.
+    pub fn limit_for(&self, name: &str) -> Option<(&str, &BuildLimit)> {
+        if let Some((key, limit)) = self.dimensions.get_key_value(name) { return Some((key, limit)); }
+        let (key, _) = self.patterns.iter().find(|(key, regex)| match regex {
+            Some(regex) => regex.is_match(name),
+            None => glob_match(key.as_bytes(), name.as_bytes()),
+        })?;
+        self.dimensions.get_key_value(key).map(|(key, limit)| (key.as_str(), limit))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format { Toml, Json5, Json }
+
+impl Format {
+    fn parse(self, content: &str) -> Result<Config, String> {
+        match self {
+            Format::Toml => toml::from_str(content).map_err(|e| e.to_string()),
+            Format::Json5 => json5::from_str(content).map_err(|e| e.to_string()),
+            Format::Json => serde_json::from_str(content).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+const ENV_PREFIX: &str = "BLC_";
+const ARG_PREFIX: &str = "--blc-";
+
+/// A setting given through the environment (`BLC_OVERWORLD_MAX=512`) or the command line
+/// (`--blc-overworld-max=512`), keyed without the prefix in upper snake case.
+pub struct Override { pub key: String, pub value: String, pub source: String }
+
+static OVERRIDES: OnceLock<Vec<Override>> = OnceLock::new();
+
+fn parse_overrides(vars: impl Iterator<Item = (String, String)>, args: impl Iterator<Item = String>) -> Vec<Override> {
+    let mut overrides: Vec<Override> = vars
+        .filter_map(|(name, value)| Some(Override { key: name.strip_prefix(ENV_PREFIX)?.to_string(), value, source: name }))
+        .collect();
+    // command line entries come last so they win over the environment
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        let Some(rest) = arg.strip_prefix(ARG_PREFIX) else { continue };
+        let (name, value) = match rest.split_once('=') {
+            Some((name, value)) => (name.to_string(), value.to_string()),
+            None => match args.next_if(|next| !next.starts_with("--")) {
+                Some(value) => (rest.to_string(), value),
+                None => continue,
+            },
+        };
+        overrides.push(Override { key: name.replace('-', "_").to_ascii_uppercase(), value, source: format!("{ARG_PREFIX}{name}") });
+    }
+    overrides.retain(|o| !o.value.is_empty());
+    overrides
+}
+
+fn overrides() -> &'static [Override] {
+    OVERRIDES.get_or_init(|| parse_overrides(std::env::vars(), std::env::args()))
+}
+
+/// The winning override for `key`, e.g. `"CONFIG_DIR"` for `BLC_CONFIG_DIR` or `--blc-config-dir`.
+pub fn override_value(key: &str) -> Option<&'static Override> {
+    overrides().iter().rev().find(|o| o.key == key)
+}
+
+impl Config {
+    /// Applies `BLC_<DIMENSION>_MIN` / `_MAX` style overrides. The dimension is matched case
+    /// insensitively against the configured names, a bound that is not given keeps the game's value.
+    fn apply_overrides(&mut self, overrides: &[Override]) {
+        for o in overrides {
+            let (dimension, is_max) = match (o.key.strip_suffix("_MIN"), o.key.strip_suffix("_MAX")) {
+                (Some(d), _) => (d, false),
+                (_, Some(d)) => (d, true),
+                _ => continue,
+            };
+            let height = match Height::from_override(&o.value) {
+                Ok(h) => h,
+                Err(e) => { log::warn!("Ignoring {}: {}", o.source, e); continue; }
+            };
+            let name = self.dimensions.keys().cloned()
+                .chain(PRESETS[0].dimensions.iter().map(|(n, _, _)| n.to_string()))
+                .find(|n| n.to_ascii_uppercase() == dimension)
+                .unwrap_or_else(|| dimension.to_string());
+            let limit = self.dimensions.entry(name).or_insert(BuildLimit { min: Height::Relative(0), max: Height::Relative(0), alignment: Alignment::default() });
+            if is_max { limit.max = height } else { limit.min = height }
+        }
+    }
+}
+
 static CONFIG_DIR: OnceLock<String> = OnceLock::new();
-const CONFIG_FILE: &str = "dimensions.json";
+static LOG_DIR: OnceLock<String> = OnceLock::new();
+/// Looked up in this order, the first file that exists is the config.
+const CONFIG_FILES: [(&str, Format); 3] = [("dimensions.toml", Format::Toml), ("dimensions.json5", Format::Json5), ("dimensions.json", Format::Json)];
+/// Written when none of the above exist, JSON5 so the defaults can carry comments.
+const DEFAULT_CONFIG_FILE: usize = 1;
 const LOG_FILE: &str = "log.txt";
+const PRESETS_DIR: &str = "presets";
+/// Written on every start so it always describes the running version.
+const SCHEMA_FILE: &str = "dimensions.schema.json";
+/// Locked while writing, game processes sharing the directory (e.g. `games/BuildLimitChanger`) take turns.
+const LOCK_FILE: &str = ".lock";
+
+fn find_config_file() -> Option<(PathBuf, Format)> {
+    let dir = Path::new(CONFIG_DIR.get()?);
+    let existing = CONFIG_FILES.iter().find(|(name, _)| dir.join(name).exists());
+    let (name, format) = existing.unwrap_or(&CONFIG_FILES[DEFAULT_CONFIG_FILE]);
+    Some((dir.join(name), *format))
+}
 
+pub fn config_dir() -> Option<&'static Path> { CONFIG_DIR.get().map(Path::new) }
 pub fn config_path() -> Option<PathBuf> { 
-    CONFIG_DIR.get().map(|d| Path::new(d).join(CONFIG_FILE)) 
+    find_config_file().map(|(path, _)| path) 
 }
 pub fn log_path() -> Option<PathBuf> { 
-    CONFIG_DIR.get().map(|d| Path::new(d).join(LOG_FILE)) 
+    LOG_DIR.get().map(|d| Path::new(d).join(LOG_FILE)) 
 }
 
 fn set_config_dir(path: String) { 
     if CONFIG_DIR.set(path).is_err() { log::error!("CONFIG_DIR can only be set once"); } 
 }
 
+/// JSON Schema for the config files, so editors can validate and complete them.
+pub fn schema() -> serde_json::Value {
+    use serde_json::json;
+    let alignments: Vec<_> = [Alignment::Expand, Alignment::Shrink, Alignment::Nearest, Alignment::Strict]
+        .iter().filter_map(|a| serde_json::to_value(a).ok()).collect();
+    let locators: Vec<_> = [ModuleLocator::Auto, ModuleLocator::Dl, ModuleLocator::Maps]
+        .iter().filter_map(|l| serde_json::to_value(l).ok()).collect();
+    let policies: Vec<_> = [TimeoutPolicy::Continue, TimeoutPolicy::Cancel, TimeoutPolicy::Wait]
+        .iter().filter_map(|p| serde_json::to_value(p).ok()).collect();
+    let presets: Vec<_> = PRESETS.iter().map(|p| json!({ "const": p.name, "description": p.description })).collect();
+    let dimensions: serde_json::Map<_, _> = PRESETS[0].dimensions.iter()
+        .map(|(name, min, max)| (name.to_string(), json!({ "$ref": "#/definitions/buildLimit", "description": format!("Vanilla range {min} to {max}") })))
+        .collect();
+    let mut properties = json!({
+        "$schema": { "type": "string" },
+        "preset": { "description": "Built-in profile applied underneath the dimensions listed in the file", "oneOf": presets },
+        "settings": {
+            "type": "object",
+            "additionalProperties": false,
+            "properties": {
+                "enabled": { "type": "boolean", "default": true, "description": "false removes the hook on the next dimension load, until the game restarts" },
+                "module_locator": { "enum": locators, "default": "auto", "description": "How the game module is found on Android/Linux" },
+                "module": { "type": "string", "description": "Library name or path holding the game code, or \"main\" for the main executable" },
+                "diagnostics": { "type": "boolean", "default": false, "description": "Write a diagnostics bundle for bug reports into the config folder on every start" },
+                "init_timeout_ms": { "type": "integer", "minimum": 0, "default": 5000, "description": "How long game startup waits for the background scan" },
+                "module_wait_ms": { "type": "integer", "minimum": 0, "default": 60000, "description": "How long to wait for the game module when the mod is loaded before it" },
+                "on_init_timeout": { "enum": policies, "default": "wait", "description": "wait: block startup until hooked, continue: start after init_timeout_ms and hook once the scan is done, cancel: start after init_timeout_ms without the hook. Only wait guarantees worlds never load with vanilla limits" },
+                "dry_run": { "type": "boolean", "default": false, "description": "Only log the build limit and mob cap changes, the game keeps its own values" },
+            },
+        },
+        "mob_caps": {
+            "type": "object",
+            "description": "Mob cap values patched at startup, by the names listed in log.txt",
+            "properties": { "water": { "$ref": "#/definitions/mobCap", "description": "The water mob cap the scan anchors on, 36 in vanilla" } },
+            "additionalProperties": { "$ref": "#/definitions/mobCap" },
+        },
+    });
+    properties.as_object_mut().unwrap().extend(dimensions);
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "BuildLimitChanger dimensions config",
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": {
+            "$ref": "#/definitions/buildLimit",
+            "description": "Dimension name, a wildcard pattern such as \"my_addon:*\", or a regex prefixed with \"re:\"",
+        },
+        "definitions": {
+            "height": {
+                "oneOf": [
+                    { "type": "integer", "minimum": i16::MIN, "maximum": i16::MAX, "description": "Absolute height, should be a multiple of 16 (a sub chunk), other values are rounded per alignment" },
+                    { "type": "string", "pattern": "^[+-]?[0-9]+$", "description": "With a sign, an offset from the game's own value, e.g. \"+128\", without one an absolute height, should be a multiple of 16" },
+                ],
+            },
+            "mobCap": { "type": "number", "minimum": 0, "maximum": MAX_MOB_CAP },
+            "buildLimit": {
+                "type": "object",
+                "required": ["min", "max"],
+                "additionalProperties": false,
+                "properties": {
+                    "min": { "$ref": "#/definitions/height" },
+                    "max": { "$ref": "#/definitions/height" },
+                    "alignment": { "enum": alignments, "default": "expand", "description": "How values off the 16 block grid are fixed up" },
+                },
+            },
+        },
+    })
+}
+
+fn write_schema() -> Result<(), ()> {
+    let dir = Path::new(CONFIG_DIR.get().ok_or_else(|| log::warn!("CONFIG_DIR is not set"))?);
+    let text = serde_json::to_string_pretty(&schema()).map_err(|e| log::warn!("Serialize failed: {e}"))? + "\n";
+    let path = dir.join(SCHEMA_FILE);
+    if fs::read_to_string(&path).is_ok_and(|old| old == text) { return Ok(()); }
+    let _lock = lock_dir(dir).map_err(|e| log::warn!("Cannot lock {}: {e}", dir.display()))?;
+    write_atomic(&path, text.as_bytes()).map_err(|e| log::warn!("Cannot write {}: {e}", path.display()))
+}
+
+/// The default config as commented JSON5, built from the vanilla preset.
+fn default_config_text() -> String {
+    let settings = Settings::default();
+    let dimensions: Vec<String> = PRESETS[0].dimensions.iter()
+        .map(|(name, min, max)| format!("  {name}: {{ min: {min}, max: {max} }},"))
+        .collect();
+    format!(r#"// BuildLimitChanger config. This file is JSON5, so comments and trailing commas are fine.
+// A dimensions.toml next to it takes priority over it, and it takes priority over dimensions.json.
+{{
+  // lets editors such as VS Code validate this file and complete the keys
+  "$schema": "./{schema_file}",
+
+  // Start from a built-in preset and only list what you change below:
+  // "vanilla", "tall", "deep_nether" or "flat_void". Full versions are in the presets folder.
+  // preset: "tall",
+
+  settings: {{
+    // false removes the hook on the next dimension load, until the game restarts
+    enabled: {enabled},
+    // how the game module is found on Android/Linux: "auto", "dl" or "maps"
+    module_locator: {locator},
+    // true writes diagnostics-<time>.tar next to this file on every start, attach it to bug reports
+    diagnostics: {diagnostics},
+    // "wait" holds game startup until hooked. "continue" and "cancel" let the game start after
+    // init_timeout_ms, "continue" hooking once the scan is done and "cancel" not at all.
+    // WARNING: with those two a world saved with extended limits can open with vanilla limits,
+    // and blocks outside the vanilla range can be lost. Servers on Linux scan in the background
+    // and wait at main, elsewhere "wait" scans while the game loads the mod.
+    on_init_timeout: {on_init_timeout},
+    init_timeout_ms: {init_timeout_ms},
+    // when the mod is loaded before the game library, how long to wait for it to be loaded
+    module_wait_ms: {module_wait_ms},
+    // true only logs the build limit and mob cap changes below, the game keeps its own values
+    dry_run: {dry_run},
+  }},
+
+  // Mob caps found next to the hooked function, patched at startup. log.txt lists their names
+  // and vanilla values, "water" is the water mob cap.
+  // mob_caps: {{ water: 36 }},
+
+  // Limits must be multiples of 16, the height of a sub chunk. Other values are rounded
+  // according to "alignment": "expand" (default), "shrink", "nearest" or "strict".
+  // Numbers are absolute, signed strings such as "+128" are relative to the game's own value.
+  //
+  // WARNING: changing min drastically alters world generation, and lowering max below
+  // the game's default changes terrain and structures too. Use new worlds or back up first.
+{dimensions}
+}}
+"#, enabled = settings.enabled, diagnostics = settings.diagnostics, init_timeout_ms = settings.init_timeout_ms, module_wait_ms = settings.module_wait_ms, dry_run = settings.dry_run,
+    on_init_timeout = serde_json::to_string(&settings.on_init_timeout).unwrap_or_default(), locator = serde_json::to_string(&settings.module_locator).unwrap_or_default(), schema_file = SCHEMA_FILE, dimensions = dimensions.join("\n"))
+}
+
+/// Exclusive lock on the config directory, released when the returned file is dropped. `None`
+/// on file systems without locks (e.g. some FUSE and sdcard mounts), writes go ahead unlocked there.
+fn lock_dir(dir: &Path) -> io::Result<Option<File>> {
+    let file = OpenOptions::new().create(true).truncate(false).write(true).open(dir.join(LOCK_FILE))?;
+    match file.lock() {
+        Ok(()) => Ok(Some(file)),
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+            log::warn!("{} does not support file locks, writing without one", dir.display());
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Replaces `path` through a synced temp file and a rename, so a crash leaves either the old
+/// or the new contents but never a truncated file.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let tmp = path.with_file_name(format!(".{name}.{}.tmp", std::process::id()));
+    let result = File::create(&tmp)
+        .and_then(|mut f| { f.write_all(contents)?; f.sync_all() })
+        .and_then(|_| fs::rename(&tmp, path));
+    if result.is_err() { let _ = fs::remove_file(&tmp); }
+    result?;
+    // the rename itself only survives power loss once the directory entry is on disk
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() { File::open(dir).and_then(|d| d.sync_all()).ok(); }
+    Ok(())
+}
+
 pub fn save() -> Result<(), ()> {
-    let defaults: BuildLimitMap = [
-        ("Overworld", BuildLimit { min: -64, max: 320 }),
-        ("Nether",    BuildLimit { min: 0,  max: 128 }),
-        ("TheEnd",    BuildLimit { min: 0,  max: 256 })
-    ].into_iter().map(|(k,v)| (k.to_string(), v)).collect();
-    fs::write(config_path().ok_or_else(
-        || { log::error!("CONFIG_DIR is not set"); () })?,
-        serde_json::to_string_pretty(&defaults)
-        .map_err(|e| log::error!("Serialize failed: {e}"))?)
+    let dir = Path::new(CONFIG_DIR.get().ok_or_else(|| { log::error!("CONFIG_DIR is not set"); })?);
+    let _lock = lock_dir(dir).map_err(|e| log::warn!("Cannot lock {}: {e}", dir.display()))?;
+    write_atomic(&dir.join(CONFIG_FILES[DEFAULT_CONFIG_FILE].0), default_config_text().as_bytes())
         .map_err(|e| log::error!("Write failed: {e}"))?;
     Ok(())
 }
 
-pub fn load() -> BuildLimitMap {
-    let path = match config_path() { 
-        Some(p) => p,
-        None => { 
-            log::error!("CONFIG_DIR not set");
-            return BuildLimitMap::new(); 
-        } 
+/// Text of the last config that loaded, for reloads that find the file broken or half saved.
+static LAST_GOOD: Mutex<Option<(String, Format)>> = Mutex::new(None);
+
+fn load_file(path: &Path, format: Format) -> Config {
+    let loaded = fs::read_to_string(path).map_err(|e| e.to_string())
+        .and_then(|content| Ok((format.parse(&content)?, content)));
+    let last_good = LAST_GOOD.lock().unwrap().clone();
+    match (loaded, last_good) {
+        (Ok((config, content)), _) => {
+            *LAST_GOOD.lock().unwrap() = Some((content, format));
+            config
+        }
+        // the config is reloaded inside the hook, log::error! there would take the game down
+        (Err(e), Some((content, format))) => {
+            log::warn!("Cannot load config {}: {e}, keeping the last one that loaded", path.display());
+            format.parse(&content).unwrap_or_default()
+        }
+        (Err(_), None) => {
+            let content = fs::read_to_string(path).unwrap_or_else(|_| {
+                save().ok();
+                fs::read_to_string(path).unwrap_or_default() 
+            });
+            format.parse(&content).unwrap_or_else(|e| { 
+                log::error!("Failed to parse config {}: {e}, regenerating default", path.display());
+                save().ok(); Config::default() 
+            })
+        }
+    }
+}
+
+pub fn load() -> Config {
+    let mut config = match find_config_file() { 
+        Some((path, format)) => load_file(&path, format),
+        // no writable config directory, only overrides can change anything
+        None => Config::default(),
     };
-    let content = fs::read_to_string(&path).unwrap_or_else(|_| {
-        save().ok();
-        fs::read_to_string(&path).unwrap_or_default() 
-    });
-    serde_json::from_str(&content).unwrap_or_else(|e| { 
-        log::error!("Failed to parse config: {e}, regenerating default");
-        save().ok(); BuildLimitMap::new() 
+    config.apply_preset();
+    config.apply_overrides(overrides());
+    config.compile_patterns();
+    config
+}
+
+/// Writes every preset as a full config into `presets/`, leaving files the user already has alone.
+pub fn export_presets() -> Result<(), ()> {
+    let dir = CONFIG_DIR.get().map(|d| Path::new(d).join(PRESETS_DIR)).ok_or_else(|| log::warn!("CONFIG_DIR is not set"))?;
+    fs::create_dir_all(&dir).map_err(|e| log::warn!("Cannot create {}: {e}", dir.display()))?;
+    let _lock = lock_dir(&dir).map_err(|e| log::warn!("Cannot lock {}: {e}", dir.display()))?;
+    for preset in PRESETS {
+        let path = dir.join(format!("{}.json", preset.name));
+        if path.exists() { continue; }
+        let content = serde_json::to_string_pretty(&preset.to_config()).map_err(|e| log::warn!("Serialize failed: {e}"))?;
+        write_atomic(&path, content.as_bytes()).map_err(|e| log::warn!("Cannot write {}: {e}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Logs where every effective dimension bound comes from, so server operators can tell
+/// an environment override from the file or the preset.
+fn log_sources() {
+    let Some((path, format)) = find_config_file() else { return };
+    let Ok(mut config) = fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|c| format.parse(&c)) else { return };
+    let in_file: Vec<String> = config.dimensions.keys().cloned().collect();
+    config.apply_preset();
+    config.apply_overrides(overrides());
+    let mut names: Vec<&String> = config.dimensions.keys().collect();
+    names.sort();
+    for name in names {
+        let limit = &config.dimensions[name];
+        for (label, height) in [("MIN", limit.min), ("MAX", limit.max)] {
+            let source = override_value(&format!("{}_{label}", name.to_ascii_uppercase()))
+                .map(|o| o.source.clone())
+                .unwrap_or_else(|| match (in_file.contains(name), &config.preset) {
+                    (false, Some(preset)) => format!("preset {preset}"),
+                    _ => path.display().to_string(),
+                });
+            log::info!("{} {} = {} ({})", name, label.to_ascii_lowercase(), height, source);
+        }
+    }
+}
+
+/// The first writable directory of `candidates`, warning about every one skipped on the way.
+fn first_writable(kind: &str, candidates: Vec<(String, String)>) -> Option<(String, String)> {
+    candidates.into_iter().find(|(dir, source)| {
+        let writable = is_dir_writable(dir);
+        if !writable { log::warn!("{} directory {} ({}) is not writable, trying the next one", kind, dir, source); }
+        writable
     })
 }
 
-pub fn init_config(path: &mut String) {    
-    path.push_str("/BuildLimitChanger/");
-    if !is_dir_writable(&path) { 
-        return log::error!("Config directory not writable: {}", path);
+pub fn init_config(platform_dirs: Vec<(String, String)>) {    
+    let config_dir = first_writable("Config", utils::config_dir_candidates(platform_dirs));
+    match &config_dir {
+        Some((dir, source)) => log::info!("Config directory: {} ({})", dir, source),
+        None => log::warn!("No writable config directory, running with the defaults"),
+    }
+    match first_writable("Log", utils::log_dir_candidates(config_dir.as_ref().map(|(d, _)| d.as_str()))) {
+        Some((dir, source)) => {
+            log::info!("Log directory: {} ({})", dir, source);
+            let _ = LOG_DIR.set(dir);
+        }
+        None => log::warn!("No writable log directory, logging to the console only"),
     }
-    set_config_dir(path.clone());
+    let Some((dir, _)) = config_dir else { return };
+    set_config_dir(dir);
     if !config_path().map_or(false, |p| p.exists()) { save().ok(); }
-}
\ No newline at end of file
+    write_schema().ok();
+    export_presets().ok();
+    log_sources();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{default_config_text, first_writable, load_file, lock_dir, parse_overrides, schema, write_atomic, preset, Alignment, BuildLimit, Config, Format, Height, ModuleLocator};
+
+    #[test]
+    fn aligned_values_are_kept() {
+        for policy in [Alignment::Expand, Alignment::Shrink, Alignment::Nearest, Alignment::Strict] {
+            assert_eq!(policy.align(-64, 320), Ok((-64, 320)));
+        }
+    }
+
+    #[test]
+    fn policies_round_in_their_direction() {
+        assert_eq!(Alignment::Expand.align(-70, 330), Ok((-80, 336)));
+        assert_eq!(Alignment::Shrink.align(-70, 330), Ok((-64, 320)));
+        assert_eq!(Alignment::Nearest.align(-70, 330), Ok((-64, 336)));
+        assert_eq!(Alignment::Nearest.align(-75, 327), Ok((-80, 320)));
+        assert!(Alignment::Strict.align(-70, 320).is_err());
+    }
+
+    #[test]
+    fn extremes_stay_in_range() {
+        assert_eq!(Alignment::Expand.align(i16::MIN, i16::MAX), Ok((i16::MIN, 32752)));
+        assert!(Alignment::Shrink.align(1, 15).is_err());
+    }
+
+    #[test]
+    fn heights_parse_as_absolute_or_relative() {
+        let limit: BuildLimit = serde_json::from_str(r#"{ "min": "-64", "max": "+128" }"#).unwrap();
+        assert_eq!((limit.min, limit.max), (Height::Relative(-64), Height::Relative(128)));
+        let limit: BuildLimit = serde_json::from_str(r#"{ "min": -64, "max": "320" }"#).unwrap();
+        assert_eq!((limit.min, limit.max), (Height::Absolute(-64), Height::Absolute(320)));
+        assert!(serde_json::from_str::<BuildLimit>(r#"{ "min": "deep", "max": 320 }"#).is_err());
+    }
+
+    #[test]
+    fn heights_resolve_and_round_trip() {
+        assert_eq!(Height::Relative(128).resolve(320), 448);
+        assert_eq!(Height::Relative(-64).resolve(-64), -128);
+        assert_eq!(Height::Relative(i16::MAX).resolve(320), i16::MAX);
+        assert_eq!(Height::Absolute(256).resolve(320), 256);
+        assert_eq!(serde_json::to_string(&Height::Relative(128)).unwrap(), r#""+128""#);
+        assert_eq!(serde_json::to_string(&Height::Relative(-64)).unwrap(), r#""-64""#);
+        assert_eq!(serde_json::to_string(&Height::Absolute(-64)).unwrap(), "-64");
+    }
+
+    #[test]
+    fn config_mixes_settings_and_dimensions() {
+        let mut config: Config = serde_json::from_str(r#"{
+            "settings": { "enabled": false },
+            "Overworld": { "min": -64, "max": "+128", "alignment": "strict" },
+            "Custom*": { "min": 0, "max": 256 }
+        }"#).unwrap();
+        config.compile_patterns();
+        assert!(!config.settings.enabled);
+        let (key, overworld) = config.limit_for("Overworld").unwrap();
+        assert_eq!((key, overworld.max, overworld.alignment), ("Overworld", Height::Relative(128), Alignment::Strict));
+        assert_eq!(config.limit_for("CustomLand").map(|(key, _)| key), Some("Custom*"));
+        assert!(config.limit_for("Nether").is_none());
+    }
+
+    #[test]
+    fn patterns_are_tried_wildcards_first_longest_first() {
+        let mut config: Config = serde_json::from_str(r#"{
+            "Overworld": { "min": 0, "max": 256 },
+            "re:.*": { "min": 0, "max": 16 },
+            "re:my_addon:(sky|deep)": { "min": 0, "max": 32 },
+            "re:[": { "min": 0, "max": 48 },
+            "my_addon:*": { "min": 0, "max": 64 },
+            "my_addon:sky*": { "min": 0, "max": 80 },
+            "Dim?": { "min": 0, "max": 96 }
+        }"#).unwrap();
+        config.compile_patterns();
+        let key = |name| config.limit_for(name).map(|(key, _)| key);
+        // an exact key beats every pattern
+        assert_eq!(key("Overworld"), Some("Overworld"));
+        assert_eq!(key("my_addon:skyland"), Some("my_addon:sky*"));
+        // the longer regex also matches, but wildcards go first
+        assert_eq!(key("my_addon:deep"), Some("my_addon:*"));
+        assert_eq!(key("Dim1"), Some("Dim?"));
+        assert_eq!(key("Dim12"), Some("re:.*"));
+        assert_eq!(key("Dim"), Some("re:.*"));
+        // the invalid regex is warned about and left out
+        assert!(config.patterns.iter().all(|(key, _)| key != "re:["));
+        config.dimensions.remove("re:.*");
+        config.compile_patterns();
+        assert_eq!(config.limit_for("Dim12").map(|(key, _)| key), None);
+    }
+
+    #[test]
+    fn regex_keys_match_the_whole_name() {
+        let mut config: Config = serde_json::from_str(r#"{ "re:my_addon:(sky|deep)": { "min": 0, "max": 32 } }"#).unwrap();
+        config.compile_patterns();
+        assert!(config.limit_for("my_addon:sky").is_some());
+        assert!(config.limit_for("my_addon:skyland").is_none());
+        assert!(config.limit_for("x_my_addon:deep").is_none());
+    }
+
+    #[test]
+    fn preset_fills_in_unlisted_dimensions() {
+        let mut config: Config = serde_json::from_str(r#"{
+            "preset": "tall",
+            "Nether": { "min": -64, "max": 128 }
+        }"#).unwrap();
+        config.apply_preset();
+        assert_eq!(config.dimensions["Overworld"].max, Height::Absolute(2032));
+        assert_eq!(config.dimensions["Nether"].min, Height::Absolute(-64));
+        assert_eq!(config.dimensions.len(), 3);
+    }
+
+    #[test]
+    fn mob_caps_are_not_dimensions() {
+        let config = Format::Toml.parse("[mob_caps]\nwater = 50\ncap_1 = 2.5\n\n[Overworld]\nmin = -64\nmax = 512\n").unwrap();
+        assert_eq!(config.mob_caps["water"], 50.0);
+        assert_eq!(config.mob_caps["cap_1"], 2.5);
+        assert_eq!(config.dimensions.keys().collect::<Vec<_>>(), ["Overworld"]);
+    }
+
+    #[test]
+    fn presets_are_aligned() {
+        for preset in super::PRESETS {
+            for &(name, min, max) in preset.dimensions {
+                assert_eq!(Alignment::Strict.align(min, max), Ok((min, max)), "{} {}", preset.name, name);
+            }
+        }
+        assert!(preset("vanilla").is_some() && preset("missing").is_none());
+    }
+
+    #[test]
+    fn default_text_round_trips_to_vanilla() {
+        let config = Format::Json5.parse(&default_config_text()).unwrap();
+        let vanilla = preset("vanilla").unwrap();
+        assert!(config.settings.enabled && config.preset.is_none());
+        assert_eq!(config.schema.as_deref(), Some("./dimensions.schema.json"));
+        assert_eq!(config.settings.module_locator, ModuleLocator::Auto);
+        assert_eq!(config.dimensions.len(), vanilla.dimensions.len());
+        for &(name, min, max) in vanilla.dimensions {
+            assert_eq!((config.dimensions[name].min, config.dimensions[name].max), (Height::Absolute(min), Height::Absolute(max)));
+        }
+    }
+
+    #[test]
+    fn toml_and_json_parse_the_same_config() {
+        let toml = Format::Toml.parse(r#"
+            preset = "tall"
+            [settings]
+            module_locator = "maps"
+            [Overworld]
+            min = -64
+            max = "+128" # relative
+            alignment = "nearest"
+        "#).unwrap();
+        let json = Format::Json.parse(r#"{
+            "preset": "tall",
+            "settings": { "module_locator": "maps" },
+            "Overworld": { "min": -64, "max": "+128", "alignment": "nearest" }
+        }"#).unwrap();
+        for config in [toml, json] {
+            assert_eq!(config.preset.as_deref(), Some("tall"));
+            assert_eq!(config.settings.module_locator, ModuleLocator::Maps);
+            let overworld = &config.dimensions["Overworld"];
+            assert_eq!((overworld.min, overworld.max, overworld.alignment), (Height::Absolute(-64), Height::Relative(128), Alignment::Nearest));
+        }
+    }
+
+    #[test]
+    fn command_line_wins_over_environment() {
+        let vars = [("BLC_OVERWORLD_MAX", "512"), ("BLC_CONFIG_DIR", "/srv/blc"), ("HOME", "/root")]
+            .map(|(k, v)| (k.to_string(), v.to_string()));
+        let args = ["bedrock_server", "--blc-overworld-max=1024", "--blc-nether-min", "-64", "--blc-empty="].map(String::from);
+        let overrides = parse_overrides(vars.into_iter(), args.into_iter());
+        let keys: Vec<(&str, &str)> = overrides.iter().map(|o| (o.key.as_str(), o.value.as_str())).collect();
+        assert_eq!(keys, [("OVERWORLD_MAX", "512"), ("CONFIG_DIR", "/srv/blc"), ("OVERWORLD_MAX", "1024"), ("NETHER_MIN", "-64")]);
+        assert_eq!(overrides[2].source, "--blc-overworld-max");
+
+        let mut config = preset("vanilla").unwrap().to_config();
+        config.apply_overrides(&overrides);
+        assert_eq!(config.dimensions["Overworld"].max, Height::Absolute(1024));
+        assert_eq!(config.dimensions["Nether"].min, Height::Absolute(-64));
+    }
+
+    #[test]
+    fn override_heights_are_absolute_unless_marked() {
+        assert_eq!(Height::from_override("-128"), Ok(Height::Absolute(-128)));
+        assert_eq!(Height::from_override("512"), Ok(Height::Absolute(512)));
+        assert_eq!(Height::from_override("+64"), Ok(Height::Relative(64)));
+        assert_eq!(Height::from_override("~-64"), Ok(Height::Relative(-64)));
+        assert_eq!(Height::from_override("~32"), Ok(Height::Relative(32)));
+        assert!(Height::from_override("~").is_err());
+    }
+
+    #[test]
+    fn overrides_match_dimensions_case_insensitively() {
+        let vars = [("BLC_THEEND_MAX", "512"), ("BLC_SKYLANDS_MAX", "+64")].map(|(k, v)| (k.to_string(), v.to_string()));
+        let mut config = Config::default();
+        config.apply_overrides(&parse_overrides(vars.into_iter(), std::iter::empty()));
+        assert_eq!(config.dimensions["TheEnd"].max, Height::Absolute(512));
+        assert_eq!(config.dimensions["TheEnd"].min, Height::Relative(0));
+        assert_eq!(config.dimensions["SKYLANDS"].max, Height::Relative(64));
+    }
+
+    #[test]
+    fn first_writable_skips_unusable_directories() {
+        let root = std::env::temp_dir().join(format!("blc-dirs-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let blocker = root.join("file");
+        std::fs::write(&blocker, b"").unwrap();
+        let candidates = [(blocker.join("config"), "override"), (root.join("config"), "platform default")]
+            .map(|(d, s)| (d.to_string_lossy().into_owned(), s.to_string()));
+        let (dir, source) = first_writable("Config", candidates.to_vec()).unwrap();
+        assert_eq!((dir.as_str(), source.as_str()), (candidates[1].0.as_str(), "platform default"));
+        assert!(first_writable("Config", candidates[..1].to_vec()).is_none());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn broken_reloads_keep_the_last_config_that_loaded() {
+        let root = std::env::temp_dir().join(format!("blc-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("dimensions.json5");
+        std::fs::write(&path, r#"{ "Overworld": { "min": -64, "max": 512 } }"#).unwrap();
+        assert_eq!(load_file(&path, Format::Json5).dimensions["Overworld"].max, Height::Absolute(512));
+        // saved halfway through an edit
+        std::fs::write(&path, r#"{ "Overworld": { "min": -64, "#).unwrap();
+        assert_eq!(load_file(&path, Format::Json5).dimensions["Overworld"].max, Height::Absolute(512));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn atomic_write_replaces_without_leftovers() {
+        let root = std::env::temp_dir().join(format!("blc-atomic-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("dimensions.json5");
+        std::fs::write(&path, b"old").unwrap();
+        {
+            let _lock = lock_dir(&root).unwrap();
+            assert!(std::fs::File::open(root.join(super::LOCK_FILE)).unwrap().try_lock().is_err());
+            write_atomic(&path, b"new").unwrap();
+        }
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        let names: Vec<_> = std::fs::read_dir(&root).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(names.len(), 2, "only the config and the lock file remain: {names:?}");
+        assert!(write_atomic(&root.join("missing").join("x.json"), b"").is_err());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn checked_in_schema_is_current() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/schema/dimensions.schema.json");
+        let text = serde_json::to_string_pretty(&schema()).unwrap() + "\n";
+        if std::env::var_os("UPDATE_SCHEMA").is_some() { std::fs::write(path, &text).unwrap(); }
+        assert_eq!(std::fs::read_to_string(path).unwrap_or_default(), text, "schema changed, rerun with UPDATE_SCHEMA=1");
+
+        let exported = serde_json::to_value(preset("tall").unwrap().to_config()).unwrap();
+        assert_eq!(exported["$schema"], "../dimensions.schema.json");
+        let parsed: Config = serde_json::from_value(exported).unwrap();
+        assert_eq!(parsed.dimensions.len(), 3);
+    }
+}
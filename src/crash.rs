@@ -0,0 +1,275 @@
+//! Crash reports for Linux/Android: on SIGSEGV/SIGABRT a `crash-<time>.txt` is written next to
+//! `log.txt` before the previous handler runs. Everything here is best effort, the process is
+//! already going down. The handler only uses async signal safe calls: no allocation, the report
+//! directory is opened up front, the text is formatted on the stack and the backtrace comes
+//! from the frame pointer chain, with modules looked up in a snapshot of `/proc/self/maps`.
+use std::{ffi::c_void, fmt::Write as _, mem, os::unix::ffi::OsStrExt, sync::{atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering}, Mutex, OnceLock}};
+
+const SIGNALS: [libc::c_int; 2] = [libc::SIGSEGV, libc::SIGABRT];
+/// Faults this close to the last patched range are reported as hitting the dimension object.
+const DIMENSION_SLACK: usize = 0x100;
+const MAX_FRAMES: usize = 32;
+/// Frame pointers further than this above the stack pointer are not trusted.
+const MAX_STACK: usize = 8 << 20;
+
+static FUNCTION_START: AtomicUsize = AtomicUsize::new(0);
+static FUNCTION_END: AtomicUsize = AtomicUsize::new(0);
+static DIMENSION_RANGE: AtomicUsize = AtomicUsize::new(0);
+static PREVIOUS: OnceLock<[libc::sigaction; 2]> = OnceLock::new();
+static REPORT_DIR: AtomicI32 = AtomicI32::new(-1);
+static HANDLING: AtomicBool = AtomicBool::new(false);
+/// Executable mappings as (start, end, file offset, file name), for module+offset in backtraces.
+static MODULES: Mutex<Vec<(usize, usize, usize, String)>> = Mutex::new(Vec::new());
+
+/// Remembers the hooked function, `end` being the next function start found by the scan.
+pub fn set_hooked_function(start: usize, end: Option<usize>) {
+    FUNCTION_START.store(start, Ordering::Relaxed);
+    FUNCTION_END.store(end.unwrap_or(start), Ordering::Relaxed);
+}
+
+/// Called by the hook before it writes a dimension range.
+pub fn set_dimension_range(addr: usize) { DIMENSION_RANGE.store(addr, Ordering::Relaxed); }
+
+/// Snapshots the executable mappings for the handler, which cannot read `/proc` itself.
+/// Called at install and again once the game module is found.
+pub fn refresh_modules() {
+    let Ok(maps) = crate::maps::read_maps() else { return };
+    let modules = maps.iter()
+        .filter(|m| m.executable() && !m.path.is_empty())
+        .map(|m| (m.start, m.end, m.offset, m.file_name().to_string()))
+        .collect();
+    if let Ok(mut current) = MODULES.lock() { *current = modules; }
+}
+
+fn module_of(modules: &[(usize, usize, usize, String)], addr: usize) -> Option<(&str, usize)> {
+    modules.iter().find(|(start, end, ..)| (*start..*end).contains(&addr))
+        .map(|(start, _, offset, name)| (name.as_str(), addr - start + offset))
+}
+
+fn locate(addr: usize, function: (usize, usize), dimension: usize) -> &'static str {
+    match addr {
+        _ if addr == 0 => "null pointer",
+        _ if function.0 != 0 && (function.0..function.1.max(function.0 + 1)).contains(&addr) => "inside the hooked function",
+        _ if dimension != 0 && addr.abs_diff(dimension) < DIMENSION_SLACK => "near the patched dimension range",
+        _ => "outside the mod's patches",
+    }
+}
+
+#[allow(unused_variables)]
+unsafe fn program_counter(context: *mut c_void) -> Option<usize> {
+    let context = (context as *const libc::ucontext_t).as_ref()?;
+    #[cfg(target_arch = "aarch64")]
+    return Some(context.uc_mcontext.pc as usize);
+    #[cfg(target_arch = "x86_64")]
+    return Some(context.uc_mcontext.gregs[libc::REG_RIP as usize] as usize);
+    #[cfg(target_arch = "x86")]
+    return Some(context.uc_mcontext.gregs[libc::REG_EIP as usize] as usize);
+    #[allow(unreachable_code)]
+    None
+}
+
+/// Frame pointer and stack pointer at the fault.
+#[allow(unused_variables)]
+unsafe fn frame_registers(context: *mut c_void) -> Option<(usize, usize)> {
+    let context = (context as *const libc::ucontext_t).as_ref()?;
+    #[cfg(target_arch = "aarch64")]
+    return Some((context.uc_mcontext.regs[29] as usize, context.uc_mcontext.sp as usize));
+    #[cfg(target_arch = "x86_64")]
+    return Some((context.uc_mcontext.gregs[libc::REG_RBP as usize] as usize, context.uc_mcontext.gregs[libc::REG_RSP as usize] as usize));
+    #[cfg(target_arch = "x86")]
+    return Some((context.uc_mcontext.gregs[libc::REG_EBP as usize] as usize, context.uc_mcontext.gregs[libc::REG_ESP as usize] as usize));
+    #[allow(unreachable_code)]
+    None
+}
+
+/// Whether `[addr, addr + len)` is mapped, `mincore` fails instead of faulting.
+fn is_mapped(addr: usize, len: usize) -> bool {
+    let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let start = addr & !(page - 1);
+    let mut vec = [0u8; 2];
+    unsafe { libc::mincore(start as *mut c_void, addr + len - start, vec.as_mut_ptr().cast()) == 0 }
+}
+
+/// Follows the saved frame pointer chain from `fp`, both architectures keep the caller's frame
+/// pointer and the return address in the two words at it. Stops at the first frame that is not
+/// above the previous one on the same stack, e.g. in code built without frame pointers.
+/// Returns how many return addresses went into `out`.
+fn walk_frames(mut fp: usize, sp: usize, out: &mut [usize]) -> usize {
+    let word = mem::size_of::<usize>();
+    let mut count = 0;
+    while count < out.len() {
+        if fp < sp || fp - sp > MAX_STACK || !fp.is_multiple_of(word) || !is_mapped(fp, word * 2) { break; }
+        let (next, ret) = unsafe { (*(fp as *const usize), *((fp + word) as *const usize)) };
+        if ret == 0 { break; }
+        out[count] = ret;
+        count += 1;
+        if next <= fp { break; }
+        fp = next;
+    }
+    count
+}
+
+/// `fmt::Write` into a stack buffer that is flushed to `fd` with `write(2)` when full.
+struct FdWriter { fd: libc::c_int, buf: [u8; 512], len: usize }
+
+impl FdWriter {
+    fn new(fd: libc::c_int) -> Self { Self { fd, buf: [0; 512], len: 0 } }
+
+    fn write_bytes(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            if self.len == self.buf.len() { self.flush(); }
+            let n = bytes.len().min(self.buf.len() - self.len);
+            self.buf[self.len..self.len + n].copy_from_slice(&bytes[..n]);
+            self.len += n;
+            bytes = &bytes[n..];
+        }
+    }
+
+    fn flush(&mut self) {
+        let mut written = 0;
+        while written < self.len {
+            let n = unsafe { libc::write(self.fd, self.buf[written..].as_ptr().cast(), self.len - written) };
+            if n <= 0 { break; }
+            written += n as usize;
+        }
+        self.len = 0;
+    }
+}
+
+impl std::fmt::Write for FdWriter {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result { self.write_bytes(s.as_bytes()); Ok(()) }
+}
+
+unsafe fn report(out: &mut FdWriter, signal: libc::c_int, info: *const libc::siginfo_t, context: *mut c_void) {
+    let function = (FUNCTION_START.load(Ordering::Relaxed), FUNCTION_END.load(Ordering::Relaxed));
+    let dimension = DIMENSION_RANGE.load(Ordering::Relaxed);
+    let name = if signal == libc::SIGSEGV { "SIGSEGV" } else { "SIGABRT" };
+    let _ = writeln!(out, "BuildLimitChanger {} crash report: {name}", env!("CARGO_PKG_VERSION"));
+    if let Some(info) = info.as_ref().filter(|_| signal == libc::SIGSEGV) {
+        let addr = info.si_addr() as usize;
+        let _ = writeln!(out, "Fault address: 0x{addr:X} ({})", locate(addr, function, dimension));
+    }
+    if let Some(pc) = program_counter(context) {
+        let _ = writeln!(out, "Program counter: 0x{pc:X} ({})", locate(pc, function, dimension));
+    }
+    let _ = writeln!(out, "Hooked function: 0x{:X}..0x{:X}", function.0, function.1);
+    let _ = writeln!(out, "Last dimension range: 0x{dimension:X}");
+    let _ = writeln!(out, "\nBacktrace:");
+    let mut frames = [0usize; MAX_FRAMES];
+    let mut count = 0;
+    if let Some(pc) = program_counter(context) { frames[0] = pc; count = 1; }
+    if let Some((fp, sp)) = frame_registers(context) { count += walk_frames(fp, sp, &mut frames[count..]); }
+    // without the snapshot the raw addresses are still worth having
+    let modules = MODULES.try_lock();
+    for (i, &addr) in frames[..count].iter().enumerate() {
+        match modules.as_deref().ok().and_then(|m| module_of(m, addr)) {
+            Some((name, offset)) => { let _ = writeln!(out, "  #{i:02} 0x{addr:X} {name}+0x{offset:X}"); }
+            None => { let _ = writeln!(out, "  #{i:02} 0x{addr:X}"); }
+        }
+    }
+    drop(modules);
+    let _ = writeln!(out, "\nLast log lines:");
+    // skipped if the crashing thread was logging, the lock is never waited for
+    if let Ok(recent) = crate::logger::LOGGER.recent.try_lock() {
+        let (old, new) = recent.slices();
+        out.write_bytes(old);
+        out.write_bytes(new);
+    }
+    out.flush();
+}
+
+extern "C" fn handle(signal: libc::c_int, info: *mut libc::siginfo_t, context: *mut c_void) {
+    let index = SIGNALS.iter().position(|&s| s == signal).unwrap_or(0);
+    if !HANDLING.swap(true, Ordering::SeqCst) {
+        let dir = REPORT_DIR.load(Ordering::Relaxed);
+        // the name is short enough to never be flushed, the writer is only its buffer
+        let mut name = FdWriter::new(-1);
+        let _ = write!(name, "crash-{}.txt\0", unsafe { libc::time(std::ptr::null_mut()) });
+        let fd = if dir < 0 { -1 } else {
+            unsafe { libc::openat(dir, name.buf.as_ptr().cast(), libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC | libc::O_CLOEXEC, 0o644) }
+        };
+        if fd >= 0 {
+            unsafe {
+                report(&mut FdWriter::new(fd), signal, info, context);
+                libc::close(fd);
+            }
+        }
+    }
+    // put the previous handler back: a fault re-executes and reaches it, an abort is re-raised
+    let Some(previous) = PREVIOUS.get() else { return };
+    unsafe {
+        libc::sigaction(signal, &previous[index], std::ptr::null_mut());
+        if signal == libc::SIGABRT { libc::raise(signal); }
+    }
+}
+
+/// Installs the handler for SIGSEGV and SIGABRT, reports go to the directory of `log.txt`.
+pub fn install() {
+    let Some(dir) = crate::config::log_path().and_then(|p| Some(p.parent()?.to_path_buf())) else {
+        return log::warn!("No log directory, crash reports disabled");
+    };
+    if PREVIOUS.get().is_some() { return log::warn!("Crash handler already installed"); }
+    let Ok(path) = std::ffi::CString::new(dir.as_os_str().as_bytes()) else { return };
+    let fd = unsafe { libc::open(path.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC) };
+    if fd < 0 { return log::warn!("Cannot open {}: {}, crash reports disabled", dir.display(), std::io::Error::last_os_error()); }
+    REPORT_DIR.store(fd, Ordering::Relaxed);
+    refresh_modules();
+    // the previous handlers are stored before ours goes in, so a crash right away can still chain
+    let mut previous: [libc::sigaction; 2] = unsafe { mem::zeroed() };
+    for (signal, previous) in SIGNALS.iter().zip(previous.iter_mut()) {
+        unsafe { libc::sigaction(*signal, std::ptr::null(), previous) };
+    }
+    let _ = PREVIOUS.set(previous);
+    for signal in SIGNALS {
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = handle as *const () as usize;
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+                log::warn!("Cannot install crash handler for signal {}: {}", signal, std::io::Error::last_os_error());
+            }
+        }
+    }
+    log::debug!("Crash reports go to {}", dir.display());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{locate, module_of, walk_frames};
+
+    #[test]
+    fn faults_are_attributed() {
+        let function = (0x1000, 0x1400);
+        assert_eq!(locate(0, function, 0), "null pointer");
+        assert_eq!(locate(0x1200, function, 0x9064), "inside the hooked function");
+        assert_eq!(locate(0x9000, function, 0x9064), "near the patched dimension range");
+        assert_eq!(locate(0x1400, function, 0), "outside the mod's patches");
+    }
+
+    #[test]
+    fn frames_are_walked_up_the_stack() {
+        // three frames of [saved frame pointer, return address], the last one ends the chain
+        let mut stack = [0usize; 6];
+        let base = stack.as_ptr() as usize;
+        let word = std::mem::size_of::<usize>();
+        stack[0] = base + 2 * word; stack[1] = 0x1111;
+        stack[2] = base + 4 * word; stack[3] = 0x2222;
+        stack[4] = 0; stack[5] = 0x3333;
+        // the walk reads the frames through raw pointers
+        let base = std::hint::black_box(&stack).as_ptr() as usize;
+        let mut out = [0usize; 8];
+        assert_eq!(walk_frames(base, base, &mut out), 3);
+        assert_eq!(out[..3], [0x1111, 0x2222, 0x3333]);
+        // a frame pointer below the stack pointer is garbage
+        assert_eq!(walk_frames(base, base + word, &mut out), 0);
+        assert_eq!(walk_frames(base, base, &mut out[..2]), 2);
+    }
+
+    #[test]
+    fn addresses_resolve_to_module_offsets() {
+        let modules = [(0x7000, 0x9000, 0x1000, String::from("libminecraftpe.so"))];
+        assert_eq!(module_of(&modules, 0x7010), Some(("libminecraftpe.so", 0x1010)));
+        assert_eq!(module_of(&modules, 0x9000), None);
+    }
+}
@@ -0,0 +1,149 @@
+//! Bug report bundles: a tar in the config directory with the config, the end of the log and
+//! what the scan and hook found, with user names stripped from paths.
+use crate::{config, hook, logger, memory, utils};
+use std::{error::Error, fmt::Write, fs, path::PathBuf, sync::Mutex, time::{SystemTime, UNIX_EPOCH}};
+
+const LOG_LINES: usize = 500;
+
+/// What `init` found, filled in as the scan goes so a failed scan still reports how far it got.
+#[derive(Default)]
+pub struct ScanReport {
+    pub text: Option<(usize, usize)>,
+    pub ranges: Vec<(usize, usize)>,
+    /// Water mob cap candidates with their distance to the previous one, from every scanned range.
+    pub caps: Vec<(usize, usize)>,
+    /// The candidate the scan went with.
+    pub cap: Option<usize>,
+    pub fn_starts: usize,
+    pub function: Option<usize>,
+}
+
+static SCAN: Mutex<Option<ScanReport>> = Mutex::new(None);
+
+pub fn record(update: impl FnOnce(&mut ScanReport)) {
+    if let Ok(mut scan) = SCAN.lock() { update(scan.get_or_insert_with(ScanReport::default)); }
+}
+
+/// FNV-1a over the readable parts of the text section, enough to tell game builds apart.
+fn text_hash(start: usize, size: usize) -> u64 {
+    memory::readable_ranges(start, size).into_iter()
+        .flat_map(|(start, size)| unsafe { std::slice::from_raw_parts(start as *const u8, size) })
+        .fold(0xCBF2_9CE4_8422_2325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01B3))
+}
+
+fn summary(reason: &str) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "BuildLimitChanger {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(out, "Reason: {reason}");
+    let _ = writeln!(out, "Platform: {} {}", std::env::consts::OS, std::env::consts::ARCH);
+    let _ = writeln!(out, "Game version: {}", utils::game_version().unwrap_or(if cfg!(target_os = "linux") { "not detected on Linux" } else { "unknown" }));
+    let launcher = match logger::LOGGER.is_levi_launcher.get() {
+        Some(true) => String::from("LeviLauncher"),
+        _ => std::env::current_exe().ok().and_then(|p| Some(p.file_name()?.to_string_lossy().into_owned())).unwrap_or_else(|| String::from("unknown")),
+    };
+    let _ = writeln!(out, "Launcher: {launcher}");
+    let _ = writeln!(out, "Hooked: {}", hook::hooked_address().map_or(String::from("no"), |addr| format!("0x{addr:X}")));
+    let scan = SCAN.lock();
+    let Some(scan) = scan.as_ref().ok().and_then(|s| s.as_ref()) else {
+        let _ = writeln!(out, "Scan: not run");
+        return out;
+    };
+    match scan.text {
+        Some((start, size)) => { let _ = writeln!(out, "Text section: 0x{start:X}+0x{size:X} FNV-1a {:016x}", text_hash(start, size)); }
+        None => { let _ = writeln!(out, "Text section: not found"); }
+    }
+    for (start, size) in &scan.ranges { let _ = writeln!(out, "Scanned range: 0x{start:X}+0x{size:X}"); }
+    for (addr, dist) in &scan.caps {
+        let chosen = if scan.cap == Some(*addr) { " (chosen)" } else { "" };
+        let _ = writeln!(out, "Water mob cap candidate: 0x{addr:X} distance 0x{dist:X}{chosen}");
+    }
+    let _ = writeln!(out, "Function starts: {}", scan.fn_starts);
+    let _ = writeln!(out, "Hooked function: {}", scan.function.map_or(String::from("none"), |addr| format!("0x{addr:X}")));
+    out
+}
+
+/// Replaces home directories and user names in paths, so bundles can be posted publicly.
+fn redact(text: &str) -> String {
+    let homes: Vec<String> = ["HOME", "USERPROFILE"].iter().filter_map(|var| std::env::var(var).ok()).collect();
+    redact_homes(text, &homes)
+}
+
+fn redact_homes(text: &str, homes: &[String]) -> String {
+    let mut text = text.to_string();
+    for home in homes.iter().filter(|h| h.len() > 1) { text = text.replace(home.as_str(), "~"); }
+    for pattern in [r#"(?i)([a-z]:[\\/]+users[\\/]+)[^\\/\s"']+"#, r#"(/home/|/Users/)[^/\s"']+"#] {
+        if let Ok(re) = regex_lite::Regex::new(pattern) { text = re.replace_all(&text, "${1}<user>").into_owned(); }
+    }
+    text
+}
+
+fn octal(field: &mut [u8], value: u64) {
+    field.copy_from_slice(format!("{:0width$o}\0", value, width = field.len() - 1).as_bytes());
+}
+
+/// Appends a plain ustar file entry, names must fit the 100 byte name field.
+fn tar_entry(out: &mut Vec<u8>, name: &str, data: &[u8], mtime: u64) {
+    let mut header = [0u8; 512];
+    header[..name.len().min(99)].copy_from_slice(&name.as_bytes()[..name.len().min(99)]);
+    octal(&mut header[100..108], 0o644);
+    octal(&mut header[108..116], 0);
+    octal(&mut header[116..124], 0);
+    octal(&mut header[124..136], data.len() as u64);
+    octal(&mut header[136..148], mtime);
+    header[148..156].fill(b' ');
+    header[156] = b'0';
+    header[257..265].copy_from_slice(b"ustar\x0000");
+    let checksum: u32 = header.iter().map(|&b| b as u32).sum();
+    header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+    out.extend_from_slice(&header);
+    out.extend_from_slice(data);
+    out.resize(out.len().next_multiple_of(512), 0);
+}
+
+/// Writes `diagnostics-<time>.tar` into the config directory and returns its path.
+pub fn write_bundle(reason: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let dir = config::config_dir().ok_or("no config directory")?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let mut tar = Vec::new();
+    tar_entry(&mut tar, "summary.txt", redact(&summary(reason)).as_bytes(), now);
+    if let Some(path) = config::config_path().filter(|p| p.exists()) {
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        tar_entry(&mut tar, &name, redact(&fs::read_to_string(&path)?).as_bytes(), now);
+    }
+    if let Some(log) = config::log_path().and_then(|p| fs::read_to_string(p).ok()) {
+        let lines: Vec<&str> = log.lines().collect();
+        let tail = lines[lines.len().saturating_sub(LOG_LINES)..].join("\n");
+        tar_entry(&mut tar, "log.txt", redact(&tail).as_bytes(), now);
+    }
+    tar.resize(tar.len() + 1024, 0);
+    let path = dir.join(format!("diagnostics-{now}.tar"));
+    config::write_atomic(&path, &tar)?;
+    log::info!("Wrote diagnostics to {}", path.display());
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_names_are_redacted() {
+        let text = redact_homes(r"C:\Users\Steve\AppData\dimensions.json /home/alex/bedrock c:/users/Bob/x /srv/mc/games", &[String::from("/srv/mc")]);
+        assert_eq!(text, r"C:\Users\<user>\AppData\dimensions.json /home/<user>/bedrock c:/users/<user>/x ~/games");
+    }
+
+    #[test]
+    fn tar_entries_are_ustar() {
+        let mut tar = Vec::new();
+        tar_entry(&mut tar, "summary.txt", b"hello", 1_700_000_000);
+        assert_eq!(tar.len(), 1024);
+        let header = &tar[..512];
+        assert_eq!(&header[..12], b"summary.txt\0");
+        assert_eq!(&header[124..136], b"00000000005\0");
+        assert_eq!(&header[257..263], b"ustar\0");
+        let stored = u32::from_str_radix(std::str::from_utf8(&header[148..154]).unwrap(), 8).unwrap();
+        let computed: u32 = header.iter().enumerate().map(|(i, &b)| if (148..156).contains(&i) { b' ' as u32 } else { b as u32 }).sum();
+        assert_eq!(stored, computed);
+        assert_eq!(&tar[512..517], b"hello");
+    }
+}
@@ -0,0 +1,21 @@
+//! Holds the server at `main` until init is done, so the scan runs in the background while the
+//! loader finishes, and still no dimension can be built before the hook is in. The constructor
+//! runs before `main`, so once the gate is in nothing of the game has run yet.
+use std::ffi::{c_char, c_int};
+
+bhook::hook_fn! {
+    fn gate(argc: c_int, argv: *mut *mut c_char, envp: *mut *mut c_char) -> c_int = {
+        crate::wait_for_init();
+        call_original(argc, argv, envp)
+    }
+}
+
+/// Hooks the executable's `main`, returns false if it is not exported or cannot be hooked.
+pub fn install() -> bool {
+    let main = unsafe { libc::dlsym(libc::RTLD_DEFAULT, c"main".as_ptr()) };
+    if main.is_null() { return false; }
+    let trampoline = unsafe { gate::hook_address(main as *mut u8) };
+    if trampoline.is_null() { return false; }
+    log::debug!("Holding main at 0x{:X} until init is done", main as usize);
+    true
+}
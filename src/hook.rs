@@ -1,51 +1,58 @@
+use crate::memory::Regions;
+use std::sync::Mutex;
+
 macro_rules! log_dim_change {
-    ($cond:expr, $name:expr, $label:expr, $old:expr, $cfg:expr, $new:expr) => {
-        if $cond { log::warn!("{} Dimension Config {} {} not divisible by 16, aligning to {}", $name, $label, $cfg, $new) }
+    ($name:expr, $label:expr, $old:expr, $cfg:expr, $new:expr, $alignment:expr) => {
+        if $cfg != $new { log::warn!("{} Dimension Config {} {} not divisible by 16, aligning to {} ({:?})", $name, $label, $cfg, $new, $alignment) }
         if $old != $new { log::info!("Changing {} Dimension {}: {} to {}", $name, $label, $old, $new) }
     };
 }
 
-macro_rules! aligned {
-    ($val:expr, $up:expr) => {{
-        let r = $val % 16;
-        if r == 0 { $val } else if $up { $val + (16 - r) } else { $val - r }
-    }};
-}
-
 macro_rules! change_range {
     ($range_addr:expr) => {
-        let range_address = $range_addr;
-        let range: i32 = std::ptr::read_volatile(range_address);
-        const MAX_NAME_LEN: usize = 15;
-       let mut name_bytes: [u8; MAX_NAME_LEN] = [0; MAX_NAME_LEN];
-        std::ptr::copy_nonoverlapping(
-            (range_address as *const u8).add(4),
-            name_bytes.as_mut_ptr(),
-            MAX_NAME_LEN,
-        );
-
-        let end = name_bytes
-            .iter()
-            .position(|&c| c == 0)
-            .unwrap_or(MAX_NAME_LEN);
-
-        let cleaned: Vec<u8> = name_bytes[..end]
-            .iter()
-            .copied()
-            .filter(|b| !b.is_ascii_control())
-            .collect();
-
-        let name = String::from_utf8_lossy(&cleaned).to_string();
-
-        log::info!("{:?}, {:?}", name.as_bytes(), name_bytes);
-        use crate::{config, utils::{combine_hex, split_hex}};
-        let (max, min) = split_hex(range);
-        let (cfg_min, cfg_max) = config::load().get(&name).map(|d| (d.min, d.max)).unwrap_or((min, max));
-        let new_min = aligned!(cfg_min, false);
-        let new_max = aligned!(cfg_max, true);
-        log_dim_change!(cfg_min % 16 != 0, name, "Min", min, cfg_min, new_min);
-        log_dim_change!(cfg_max % 16 != 0, name, "Max", max, cfg_max, new_max);
-        *range_address = combine_hex(new_max, new_min);
+        use crate::{api, config, memory, std_string, utils::{combine_hex, split_hex}};
+        let range_address = $range_addr as usize;
+        let config = config::load();
+        if !config.settings.enabled {
+            log::info!("BuildLimitChanger disabled in config, removing hook");
+            crate::hook::uninstall_hook();
+        }
+        let result: Result<(), memory::MemoryError> = (|| {
+            if !config.settings.enabled { return Ok(()); }
+            let regions = memory::Regions::snapshot();
+            let range: i32 = regions.read(range_address)?;
+            let name = std_string::read(&regions, range_address + 4)?;
+            log::debug!("Dimension name: {:?}", name);
+            let (max, min) = split_hex(range);
+            let (cfg_min, cfg_max, alignment) = api::override_for(&name).map(|l| (l.min, l.max, config::Alignment::default()))
+                .or_else(|| config.limit_for(&name).map(|(key, d)| {
+                    if key != name { log::info!("{} Dimension matched config entry {}", name, key); }
+                    let (cfg_min, cfg_max) = (d.min.resolve(min), d.max.resolve(max));
+                    if d.min.absolute().is_none() { log::info!("{} Dimension Min {} relative to {} is {}", name, d.min, min, cfg_min); }
+                    if d.max.absolute().is_none() { log::info!("{} Dimension Max {} relative to {} is {}", name, d.max, max, cfg_max); }
+                    (cfg_min, cfg_max, d.alignment)
+                }))
+                .unwrap_or((min, max, config::Alignment::default()));
+            let (new_min, new_max) = match alignment.align(cfg_min, cfg_max) {
+                Ok(aligned) => aligned,
+                Err(reason) => {
+                    log::warn!("{} Dimension Config {} to {} rejected by {:?} alignment: {}, keeping {} to {}", name, cfg_min, cfg_max, alignment, reason, min, max);
+                    return Ok(());
+                }
+            };
+            log_dim_change!(name, "Min", min, cfg_min, new_min, alignment);
+            log_dim_change!(name, "Max", max, cfg_max, new_max, alignment);
+            if config.settings.dry_run && (min, max) != (new_min, new_max) {
+                log::info!("Dry run, not writing: {} Dimension keeps {} to {}", name, min, max);
+                return Ok(());
+            }
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            crate::crash::set_dimension_range(range_address);
+            regions.write(range_address, combine_hex(new_max, new_min))?;
+            api::notify_range_change(&name, api::BlcBuildLimit { min: new_min, max: new_max });
+            Ok(())
+        })();
+        if let Err(e) = result { log::warn!("Leaving dimension range untouched: {e}"); }
     };
 }
 #[cfg(target_arch = "aarch64")] // only on android
@@ -78,7 +85,237 @@ bhook::hook_fn! {
     }
 }
 
-pub fn setup_hook(function_addr: usize) {
-    unsafe { hook::hook_address(function_addr as *mut u8) };
+/// Bytes saved from the start of the target before the detour is written over them.
+const SAVED_PROLOGUE_LEN: usize = 32;
+/// Instructions decoded at the trampoline to check it is real code.
+const CHECKED_INSTRUCTIONS: usize = 4;
+/// Branches followed from the prologue, hooking libraries may go through a stub or two.
+const MAX_BRANCH_HOPS: usize = 4;
+
+#[derive(Debug)]
+pub enum HookError {
+    Memory(crate::memory::MemoryError),
+    /// The bytes at the address do not start a function the way the scanner looks for them.
+    UnexpectedPrologue { addr: usize, bytes: Vec<u8> },
+    /// The prologue still runs the original code after hooking.
+    NotRedirected { addr: usize },
+    /// The prologue branches, but not to our detour.
+    WrongBranchTarget { addr: usize, target: usize, detour: usize },
+    /// The trampoline that calls the original function does not decode as code.
+    InvalidTrampoline { addr: usize, trampoline: usize },
+}
+
+impl std::fmt::Display for HookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            HookError::Memory(e) => write!(f, "{e}"),
+            HookError::UnexpectedPrologue { addr, bytes } => write!(f, "0x{addr:X} does not look like a function start: {bytes:02X?}"),
+            HookError::NotRedirected { addr } => write!(f, "0x{addr:X} does not branch to the detour after hooking"),
+            HookError::WrongBranchTarget { addr, target, detour } => write!(f, "0x{addr:X} branches to 0x{target:X} instead of the detour at 0x{detour:X}"),
+            HookError::InvalidTrampoline { addr, trampoline } => write!(f, "trampoline 0x{trampoline:X} for 0x{addr:X} is not valid code"),
+        }
+    }
+}
+
+impl std::error::Error for HookError {}
+
+impl From<crate::memory::MemoryError> for HookError {
+    fn from(e: crate::memory::MemoryError) -> Self { HookError::Memory(e) }
+}
+
+/// Mirrors what the scanner accepts as the first instruction after a `ret`.
+fn looks_like_function_start(code: &[u8], addr: usize) -> bool {
+    #[cfg(target_arch = "aarch64")] {
+        let _ = addr;
+        code.get(..4).is_some_and(|w| u32::from_le_bytes(w.try_into().unwrap()) & 0xFF00_0000 == 0xD100_0000)
+    }
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))] {
+        let Some(instruction) = decode_x86(code, addr).next() else { return false };
+        #[cfg(all(target_os = "windows", target_arch = "x86_64"))]
+        return instruction.mnemonic() == iced_x86::Mnemonic::Mov;
+        #[cfg(not(all(target_os = "windows", target_arch = "x86_64")))]
+        return instruction.mnemonic() == iced_x86::Mnemonic::Push;
+    }
+}
+
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+fn decode_x86(code: &[u8], addr: usize) -> impl Iterator<Item = iced_x86::Instruction> + '_ {
+    #[cfg(target_arch = "x86_64")] const BITNESS: u32 = 64;
+    #[cfg(target_arch = "x86")] const BITNESS: u32 = 32;
+    iced_x86::Decoder::with_ip(BITNESS, code, addr as u64, iced_x86::DecoderOptions::NONE).into_iter()
+}
+
+/// Where the code at `addr` unconditionally jumps to, `read` supplies pointer sized literals
+/// for the absolute jump forms hooking libraries write.
+fn branch_target(code: &[u8], addr: usize, read: impl Fn(usize) -> Option<usize>) -> Option<usize> {
+    #[cfg(target_arch = "aarch64")] {
+        let word = |i: usize| code.get(i * 4..i * 4 + 4).map(|w| u32::from_le_bytes(w.try_into().unwrap()));
+        let first = word(0)?;
+        // b <imm26>
+        if first & 0xFC00_0000 == 0x1400_0000 {
+            let offset = (((first & 0x03FF_FFFF) << 6) as i32 >> 4) as isize;
+            return Some(addr.wrapping_add_signed(offset));
+        }
+        // ldr xN, <literal>; br xN
+        let reg = first & 0x1F;
+        if first & 0xFF00_0000 == 0x5800_0000 && word(1)? == 0xD61F_0000 | reg << 5 {
+            let literal = addr + (((first >> 5) & 0x7_FFFF) as usize) * 4;
+            return read(literal);
+        }
+        None
+    }
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))] {
+        use iced_x86::{Mnemonic, OpKind};
+        let mut instructions = decode_x86(code, addr);
+        let first = instructions.next()?;
+        match (first.mnemonic(), first.op0_kind()) {
+            (Mnemonic::Jmp, OpKind::NearBranch16 | OpKind::NearBranch32 | OpKind::NearBranch64) => Some(first.near_branch_target() as usize),
+            // jmp [rip + disp] with the destination stored behind it
+            (Mnemonic::Jmp, OpKind::Memory) if first.is_ip_rel_memory_operand() => read(first.ip_rel_memory_address() as usize),
+            // mov reg, imm; jmp reg
+            (Mnemonic::Mov, OpKind::Register) if matches!(first.op1_kind(), OpKind::Immediate64 | OpKind::Immediate32) => {
+                let jump = instructions.next()?;
+                let same = jump.mnemonic() == Mnemonic::Jmp && jump.op0_kind() == OpKind::Register && jump.op0_register() == first.op0_register();
+                same.then(|| first.immediate(1) as usize)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Whether the first few instructions at `code` decode to something a CPU would run.
+fn decodes_as_code(code: &[u8], addr: usize) -> bool {
+    #[cfg(target_arch = "aarch64")] {
+        let _ = addr;
+        // permanently undefined (udf) and all zero words are what unmapped or data pages look like
+        code.chunks_exact(4).take(CHECKED_INSTRUCTIONS).count() == CHECKED_INSTRUCTIONS
+            && code.chunks_exact(4).take(CHECKED_INSTRUCTIONS).all(|w| u32::from_le_bytes(w.try_into().unwrap()) >> 16 != 0)
+    }
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))] {
+        let instructions: Vec<_> = decode_x86(code, addr).take(CHECKED_INSTRUCTIONS).collect();
+        instructions.len() == CHECKED_INSTRUCTIONS && instructions.iter().all(|i| !i.is_invalid())
+    }
+}
+
+fn read_prologue(regions: &Regions, addr: usize) -> Result<[u8; SAVED_PROLOGUE_LEN], HookError> {
+    let mut bytes = [0u8; SAVED_PROLOGUE_LEN];
+    regions.read_bytes(addr, &mut bytes)?;
+    Ok(bytes)
+}
+
+/// Follows the branch at `addr` through any stubs until it reaches `detour`. `code` reads the
+/// bytes at an address and `read` pointer sized literals.
+fn follows_to(addr: usize, detour: usize, code: impl Fn(usize) -> Option<[u8; SAVED_PROLOGUE_LEN]>, read: impl Fn(usize) -> Option<usize>) -> Result<(), HookError> {
+    let mut at = addr;
+    for _ in 0..MAX_BRANCH_HOPS {
+        let Some(target) = code(at).and_then(|bytes| branch_target(&bytes, at, &read)) else { break };
+        if target == detour { return Ok(()); }
+        at = target;
+    }
+    Err(if at == addr { HookError::NotRedirected { addr } } else { HookError::WrongBranchTarget { addr, target: at, detour } })
+}
+
+/// Checks the freshly written prologue at `addr` branches to `detour`, and that `trampoline`,
+/// which runs the saved original instructions, decodes as code.
+fn verify_redirect(addr: usize, detour: usize, trampoline: usize) -> Result<(), HookError> {
+    let regions = Regions::snapshot();
+    follows_to(addr, detour, |at| read_prologue(&regions, at).ok(), |literal| regions.read::<usize>(literal).ok())?;
+    let mut code = [0u8; SAVED_PROLOGUE_LEN];
+    regions.read_bytes(trampoline, &mut code).map_err(|_| HookError::InvalidTrampoline { addr, trampoline })?;
+    if trampoline == 0 || !decodes_as_code(&code, trampoline) { return Err(HookError::InvalidTrampoline { addr, trampoline }); }
+    log::debug!("0x{:X} now branches to the detour at 0x{:X}, trampoline at 0x{:X}", addr, detour, trampoline);
+    Ok(())
+}
+
+pub struct HookHandle { target: usize, original: [u8; SAVED_PROLOGUE_LEN] }
+
+impl HookHandle {
+    /// Writes the saved prologue back, the trampoline stays allocated so callers already inside it can return.
+    pub fn uninstall(self) -> Result<(), Box<dyn std::error::Error>> {
+        crate::memory::patch_code(self.target, &self.original)?;
+        log::info!("Unhooked function at 0x{:X}", self.target);
+        Ok(())
+    }
+}
+
+static HOOK: Mutex<Option<HookHandle>> = Mutex::new(None);
+
+/// Hooks the function at `function_addr` and checks the result. On any failure the function
+/// is left as the game wrote it, so the game can run on unmodified.
+pub fn setup_hook(function_addr: usize) -> Result<(), HookError> {
+    let original = read_prologue(&Regions::snapshot(), function_addr)?;
+    if !looks_like_function_start(&original, function_addr) {
+        return Err(HookError::UnexpectedPrologue { addr: function_addr, bytes: original.to_vec() });
+    }
+    let trampoline = unsafe { hook::hook_address(function_addr as *mut u8) } as usize;
+    let handle = HookHandle { target: function_addr, original };
+    if let Err(e) = verify_redirect(function_addr, hook::detour as *const () as usize, trampoline) {
+        // bhook may not have written anything, restoring the saved bytes is harmless either way
+        handle.uninstall().map_err(|restore| log::warn!("Cannot restore 0x{:X}: {restore}", function_addr)).ok();
+        return Err(e);
+    }
+    *HOOK.lock().unwrap() = Some(handle);
     log::debug!("Hooked function at 0x{:X}", function_addr);
-}
\ No newline at end of file
+    Ok(())
+}
+
+pub fn is_hooked() -> bool { HOOK.lock().is_ok_and(|h| h.is_some()) }
+
+pub fn hooked_address() -> Option<usize> { HOOK.lock().ok()?.as_ref().map(|h| h.target) }
+
+/// Removes the detour if it is installed, returns whether the game now runs the original function.
+pub fn uninstall_hook() -> bool {
+    let Some(handle) = HOOK.lock().ok().and_then(|mut h| h.take()) else { return false };
+    let unhooked = handle.uninstall().map_err(|e| log::warn!("Failed to unhook: {e}")).is_ok();
+    // after the prologue, which was saved with any caps in it already patched
+    crate::mob_caps::restore();
+    unhooked
+}
+
+#[cfg(all(test, any(target_arch = "x86_64", target_arch = "x86")))]
+mod tests {
+    use super::*;
+
+    const ADDR: usize = 0x10_0000;
+
+    #[test]
+    fn relative_and_absolute_jumps_are_followed() {
+        // jmp rel32 +0x100
+        assert_eq!(branch_target(&[0xE9, 0xFB, 0x00, 0x00, 0x00], ADDR, |_| None), Some(ADDR + 0x100));
+        // mov rax, imm64; jmp rax
+        let mut mov_jmp = vec![0x48, 0xB8];
+        mov_jmp.extend_from_slice(&0x7F00_1234_5678u64.to_le_bytes());
+        mov_jmp.extend_from_slice(&[0xFF, 0xE0]);
+        assert_eq!(branch_target(&mov_jmp, ADDR, |_| None), Some(0x7F00_1234_5678));
+        // jmp [rip+0] followed by the destination
+        assert_eq!(branch_target(&[0xFF, 0x25, 0, 0, 0, 0], ADDR, |lit| (lit == ADDR + 6).then_some(0xCAFE)), Some(0xCAFE));
+        // the unhooked prologue runs on
+        assert_eq!(branch_target(&[0x55, 0x48, 0x89, 0xE5], ADDR, |_| None), None);
+    }
+
+    #[test]
+    fn redirects_must_reach_the_detour() {
+        const STUB: usize = ADDR + 0x1000;
+        const DETOUR: usize = ADDR + 0x2000;
+        // jmp rel32 to the stub, which does jmp [rip+0] to the detour
+        let code = |at: usize| {
+            let mut bytes = [0x90u8; SAVED_PROLOGUE_LEN];
+            match at {
+                ADDR => bytes[..5].copy_from_slice(&[0xE9, 0xFB, 0x0F, 0x00, 0x00]),
+                STUB => bytes[..6].copy_from_slice(&[0xFF, 0x25, 0, 0, 0, 0]),
+                _ => return None,
+            }
+            Some(bytes)
+        };
+        let literal = |target: usize| move |lit: usize| (lit == STUB + 6).then_some(target);
+        assert!(follows_to(ADDR, DETOUR, code, literal(DETOUR)).is_ok());
+        assert!(matches!(follows_to(ADDR, DETOUR, code, literal(0xBAD)), Err(HookError::WrongBranchTarget { target: 0xBAD, .. })));
+        assert!(matches!(follows_to(ADDR, DETOUR, |_| Some([0x55; SAVED_PROLOGUE_LEN]), literal(DETOUR)), Err(HookError::NotRedirected { .. })));
+    }
+
+    #[test]
+    fn garbage_does_not_decode_as_code() {
+        assert!(decodes_as_code(&[0x55, 0x48, 0x89, 0xE5, 0x53, 0x48, 0x83, 0xEC, 0x28], ADDR));
+        assert!(!decodes_as_code(&[0x0F, 0x0B, 0xFF, 0xFF, 0x0F, 0x37, 0xD6, 0x62], ADDR));
+    }
+}
@@ -1,138 +1,238 @@
 #![allow(non_snake_case)]
+mod api;
 mod config;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+mod crash;
+mod diagnostics;
+#[cfg(target_os = "linux")]
+mod gate;
 mod hook;
 mod logger;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+mod maps;
+mod memory;
+mod mob_caps;
+mod scan;
+#[cfg(target_os = "linux")]
+mod status;
+mod std_string;
 mod utils;
 
-fn find_water_mob_cap_and_fn_starts(data: &[u8]) -> (Option<usize>, Vec<usize>) {
-    let mut seen_ret = false;
-    let mut possible_fn_starts = Vec::new();
-    let mut last_possible_water_mob_cap: Option<usize> = None;
-    let mut water_mob_cap: Option<usize> = None;
-    let mut closest_distance = usize::MAX;
+use std::{sync::{Condvar, Mutex}, time::Duration};
 
-    #[cfg(target_arch = "aarch64")] {
-        const MASKS: [u32; 3] = [0xFFFF_FC1F, 0xFFFF_FFE0, 0xFF00_0000];
-        const PATTERNS: [u32; 3] = [0xD65F_0000, 0x52A8_4200, 0xD100_0000];
-        const LMAO: u32 = 0xF2E8_4200;
-        for inst in data.chunks_exact(4) {
-            let addr = inst.as_ptr() as usize;
-            let instr = u32::from_le_bytes(inst.try_into().unwrap());
-            if (instr & MASKS[0]) == PATTERNS[0] {
-                seen_ret = true;
-            } else if seen_ret && (instr & MASKS[2]) == PATTERNS[2] {
-                possible_fn_starts.push(addr);
-                seen_ret = false;
-            } else {
-                let masked = instr & MASKS[1];
-                if !(masked == PATTERNS[1] || masked == LMAO) {
-                    continue;
-                }
-                log::debug!("instr {:X}", instr);
-                if let Some(prev) = last_possible_water_mob_cap {
-                    let dist = addr.wrapping_sub(prev);
-                    if dist < closest_distance {
-                        closest_distance = dist;
-                        water_mob_cap = Some(addr);
-                    }
-                }
-                last_possible_water_mob_cap = Some(addr);
-            }
-        }
+const INIT_THREAD: &str = "blc-init";
+
+/// Tells the launcher why the game runs without the hook and writes a diagnostics bundle.
+fn report_unhooked(reason: &str) {
+    #[cfg(target_os = "linux")]
+    status::report(&format!("failed {reason}"));
+    diagnostics::write_bundle(reason).map_err(|e| log::warn!("Cannot write diagnostics: {e}")).ok();
+}
+
+/// Writes a diagnostics bundle before the fatal log, the logger panics on errors. On the init
+/// thread the game may already have a world open, so there it only warns and runs unmodified.
+fn scan_failed(reason: &str) {
+    report_unhooked(reason);
+    if std::thread::current().name() == Some(INIT_THREAD) { return log::warn!("{reason}, the game keeps running without the hook"); }
+    log::error!("{}", reason);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum InitState { Running, Done, Cancelled }
+
+static INIT_STATE: Mutex<InitState> = Mutex::new(InitState::Running);
+static INIT_DONE: Condvar = Condvar::new();
+
+/// Marks init finished even if it bails out early, so the loading thread stops waiting.
+struct InitGuard;
+
+impl Drop for InitGuard {
+    fn drop(&mut self) {
+        if let Ok(mut state) = INIT_STATE.lock() { if *state == InitState::Running { *state = InitState::Done; } }
+        INIT_DONE.notify_all();
     }
-    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))] {
-        use iced_x86::{Decoder, Instruction, Mnemonic};
-        #[cfg(target_arch = "x86_64")] const BITNESS: u32 = 64;
-        #[cfg(target_arch = "x86")] const BITNESS: u32 = 32;
-        let mut decoder = Decoder::new(BITNESS, data, iced_x86::DecoderOptions::NO_INVALID_CHECK);
-        decoder.set_ip(data.as_ptr() as u64);
-        let mut instruction = Instruction::default();
+}
 
-        const TARGET_IMMEDIATE: u64 = 0x42100000;
-        const TARGET_IMMEDIATE2: u64 = 0x40000000;
-        let mut lastTarget: u64 = 0;
-        while decoder.can_decode() {
-            decoder.decode_out(&mut instruction);
+/// Starts `init` on a background thread. On Linux the server is held at `main` until init is
+/// done, elsewhere, or if `main` cannot be hooked, `Wait` scans inline and the other policies
+/// wait for up to `init_timeout_ms` here.
+fn start_init() {
+    let settings = config::load().settings;
+    // if the game library comes after us, blocking here would only hold up its loading
+    let loaded = utils::module_loaded(utils::target_module().as_deref());
+    #[cfg(target_os = "linux")]
+    let gated = loaded && gate::install();
+    #[cfg(not(target_os = "linux"))]
+    let gated = false;
+    if loaded && !gated && settings.on_init_timeout == config::TimeoutPolicy::Wait { return init(); }
+    let started = std::thread::Builder::new().name(String::from(INIT_THREAD)).spawn(|| {
+        let _guard = InitGuard;
+        init();
+    });
+    if let Err(e) = started {
+        log::warn!("Cannot start the init thread: {e}, scanning on the loading thread");
+        let _guard = InitGuard;
+        return init();
+    }
+    if !loaded { return log::info!("Game module not loaded yet, hooking as soon as it is"); }
+    // DllMain holds the loader lock, the new thread cannot even start until we return
+    if cfg!(target_os = "windows") { return log::debug!("Scanning in the background"); }
+    if gated { return log::debug!("Scanning in the background, main waits for it"); }
+    wait_for_init();
+}
 
-            match instruction.mnemonic() {
-                Mnemonic::Ret => seen_ret = true,
-                Mnemonic::Mov => {
-                    #[cfg(all(target_os = "windows", target_arch = "x86_64"))]
-                    if seen_ret {
-                        possible_fn_starts.push(instruction.ip() as usize);
-                        seen_ret = false;
-                        continue;
-                    }
-                    let current = instruction.try_immediate(1).unwrap_or(0);
-                    let is_target = matches!(current, TARGET_IMMEDIATE | TARGET_IMMEDIATE2);
-                    if is_target && lastTarget != current {
-                        lastTarget = current;
-                        let addr = instruction.ip() as usize;
-                        if let Some(prev) = last_possible_water_mob_cap {
-                            let dist = addr.wrapping_sub(prev);
-                            if dist < closest_distance {
-                                closest_distance = dist;
-                                water_mob_cap = Some(addr);
-                            }
-                        }
-                        last_possible_water_mob_cap = Some(addr);
-                    }
-                },
-                #[cfg(any(target_os = "android", target_os = "linux", all(target_os = "windows", target_arch = "x86")))]
-                Mnemonic::Push if seen_ret => {
-                    possible_fn_starts.push(instruction.ip() as usize);
-                    seen_ret = false;
-                }
-                _ => {}
-            }
+/// Blocks until init is done: for good with `Wait`, otherwise for up to `init_timeout_ms`,
+/// after which the policy decides.
+fn wait_for_init() {
+    let settings = config::load().settings;
+    let Ok(state) = INIT_STATE.lock() else { return };
+    if settings.on_init_timeout == config::TimeoutPolicy::Wait {
+        INIT_DONE.wait_while(state, |s| *s == InitState::Running).ok();
+        return;
+    }
+    let timeout = Duration::from_millis(settings.init_timeout_ms);
+    let Ok((mut state, wait)) = INIT_DONE.wait_timeout_while(state, timeout, |s| *s == InitState::Running) else { return };
+    if !wait.timed_out() { return; }
+    match settings.on_init_timeout {
+        config::TimeoutPolicy::Cancel => {
+            *state = InitState::Cancelled;
+            log::warn!("Scan not done after {} ms, starting the game without the hook", settings.init_timeout_ms);
         }
+        _ => log::warn!("Scan not done after {} ms, hooking in the background, dimensions loaded before then keep their limits", settings.init_timeout_ms),
     }
-
-    (water_mob_cap, possible_fn_starts)
 }
 
 #[cfg_attr(target_os = "android", no_mangle)]
 fn init() {
+    scan();
+    if config::load().settings.diagnostics {
+        diagnostics::write_bundle("requested in config").map_err(|e| log::warn!("Cannot write diagnostics: {e}")).ok();
+    }
+}
+
+fn scan() {
+    let module = utils::target_module();
+    if !utils::module_loaded(module.as_deref()) {
+        let name = module.as_deref().unwrap_or("main executable");
+        let timeout = Duration::from_millis(config::load().settings.module_wait_ms);
+        log::info!("{name} is not loaded yet, waiting up to {timeout:?} for it");
+        let wait_start = std::time::Instant::now();
+        if !utils::wait_for_module(module.as_deref(), timeout) {
+            // the game may run without it, e.g. a mistyped `module`, so this must not abort
+            #[cfg(target_os = "linux")]
+            status::report(&format!("failed {name} not loaded after {timeout:?}"));
+            return log::warn!("{name} not loaded after {timeout:?}, not hooking, check `module` in the config");
+        }
+        log::info!("{name} loaded after {:?}", wait_start.elapsed());
+    }
     let time_start = std::time::Instant::now();
-    let mcmap = utils::find_minecraft_text_section().unwrap_or_else(|e|  { panic!("Cannot find Minecraft .text section: {}", e.to_string()) });
-    let data = unsafe { std::slice::from_raw_parts(mcmap.start as *const u8, mcmap.size) };
-    let (water_mob_cap, possible_fn_starts) = find_water_mob_cap_and_fn_starts(data);
-    if water_mob_cap.is_none() { return log::error!("Cannot find the water mob cap"); }
-    let Some(function_addr) = utils::find_max_less_than(&possible_fn_starts, water_mob_cap.unwrap()) else {
-        return log::error!("Cannot get the function where water mob cap is located");
+    let mcmap = match utils::find_minecraft_text_section() {
+        Ok(mcmap) => mcmap,
+        Err(e) => return scan_failed(&format!("Cannot find Minecraft .text section: {e}")),
     };
+    diagnostics::record(|scan| scan.text = Some((mcmap.start, mcmap.size)));
+    let ranges = memory::readable_ranges(mcmap.start, mcmap.size);
+    diagnostics::record(|scan| scan.ranges = ranges.clone());
+    if ranges != [(mcmap.start, mcmap.size)] {
+        log::warn!("Text section 0x{:X}+0x{:X} is only partially readable, scanning {} ranges", mcmap.start, mcmap.size, ranges.len());
+    }
+    let mut water_mob_cap: Option<(usize, usize)> = None;
+    let mut possible_fn_starts = Vec::new();
+    for (start, size) in ranges {
+        let data = unsafe { std::slice::from_raw_parts(start as *const u8, size) };
+        let scan::ScanResult { cap, candidates, fn_starts } = scan::scan(data);
+        diagnostics::record(|scan| scan.caps.extend(candidates));
+        if let Some((addr, dist)) = cap {
+            if water_mob_cap.is_none_or(|(_, best)| dist < best) { water_mob_cap = Some((addr, dist)); }
+        }
+        possible_fn_starts.extend(fn_starts);
+    }
+    diagnostics::record(|scan| scan.fn_starts = possible_fn_starts.len());
+    let Some((water_mob_cap, _)) = water_mob_cap else { return scan_failed("Cannot find the water mob cap"); };
+    diagnostics::record(|scan| scan.cap = Some(water_mob_cap));
+    let Some(function_addr) = utils::find_max_less_than(&possible_fn_starts, water_mob_cap) else {
+        return scan_failed("Cannot get the function where water mob cap is located");
+    };
+    diagnostics::record(|scan| scan.function = Some(function_addr));
+    let function_end = possible_fn_starts.iter().copied().filter(|&s| s > function_addr).min();
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    {
+        crash::set_hooked_function(function_addr, function_end);
+        // the game module may have loaded after the handler went in
+        crash::refresh_modules();
+    }
     log::debug!("Function Offset: 0x{:X}", function_addr);
-    log::debug!("{:02X?}", &data[function_addr - mcmap.start..(function_addr - mcmap.start + 50).min(data.len())]);
-    hook::setup_hook(function_addr);
+    let mut prologue = [0u8; 50];
+    match memory::read_bytes(function_addr, &mut prologue) {
+        Ok(()) => log::debug!("{:02X?}", prologue),
+        Err(e) => log::warn!("Cannot dump function prologue: {e}"),
+    }
+    // hooking under the state lock, so a timeout cancelling init cannot race the install
+    let Ok(mut state) = INIT_STATE.lock() else { return };
+    let settings = config::load().settings;
+    // nobody waited on Windows, so the scan applies the timeout to itself
+    if cfg!(target_os = "windows") && settings.on_init_timeout == config::TimeoutPolicy::Cancel
+        && time_start.elapsed() > Duration::from_millis(settings.init_timeout_ms) { *state = InitState::Cancelled; }
+    if *state == InitState::Cancelled {
+        #[cfg(target_os = "linux")]
+        status::report("cancelled");
+        return log::warn!("Init was cancelled by the startup timeout, not hooking");
+    }
+    // hooking only for the detour to unhook again on the first dimension load would be pointless patching
+    if !settings.enabled {
+        #[cfg(target_os = "linux")]
+        status::report("disabled");
+        return log::info!("Disabled in the config, not hooking and leaving mob caps alone");
+    }
+    // before the hook goes in, so a cap in the prologue ends up in the saved copy
+    mob_caps::apply(function_addr, function_end.unwrap_or(water_mob_cap + 16), water_mob_cap);
+    if let Err(e) = hook::setup_hook(function_addr) {
+        // setup_hook put the prologue back, the caps patched above go back after it
+        mob_caps::restore();
+        debug_assert!(!mob_caps::is_patched(), "mob caps still patched after a failed hook");
+        report_unhooked(&format!("Hook not installed: {e}"));
+        return log::warn!("Hook not installed, the game runs unmodified: {e}");
+    }
+    #[cfg(target_os = "linux")]
+    status::report(&format!("hooked 0x{function_addr:X}"));
     log::info!("Took: {:?}", time_start.elapsed());
 }
 
-#[ctor::ctor]
+#[cfg_attr(not(test), ctor::ctor)]
 fn main() {
     println!("Starting BuildLimitChanger");
     log::set_logger(&logger::LOGGER).expect("Logger already set");
     log::set_max_level(log::LevelFilter::Debug);
     #[cfg(any(target_os = "linux", target_os = "windows"))] {
-        config::init_config(&mut utils::get_config_directory().expect("Failed to get a valid config directory"));
+        #[cfg(target_os = "linux")]
+        status::init();
+        config::init_config(utils::get_config_directories());
         logger::init_log_file(false);
-        init(); 
+        #[cfg(target_os = "linux")]
+        crash::install();
+        #[cfg(target_os = "windows")]
+        if let Some(version) = utils::detect_game_version() { utils::set_game_version(version); }
+        start_init();
     } 
 }
 
 #[cfg(target_os = "android")]
 #[no_mangle]
 pub extern "C" fn mod_init() {
-    config::init_config(&mut String::from("/data/data/com.mojang.minecraftpe"));
+    config::init_config(vec![(String::from("/data/data/com.mojang.minecraftpe"), String::from("platform default"))]);
     logger::init_log_file(false);
-    init();
+    crash::install();
+    start_init();
 }
 
 #[cfg(target_os = "android")]
 #[no_mangle]
 pub extern "system" fn JNI_OnLoad(vm: jni::JavaVM, _: *mut core::ffi::c_void) -> i32 {
     let mut env = vm.get_env().expect("Cannot get reference to the JNIEnv");
-    config::init_config(&mut utils::get_config_directory(&mut env).expect("Failed to get a valid config directory"));
+    config::init_config(utils::get_config_directories(&mut env));
     logger::init_log_file(utils::is_levi_launcher(&mut env));
-    init();
+    crash::install();
+    if let Some(version) = utils::detect_game_version(&mut env) { utils::set_game_version(version); }
+    start_init();
     return jni::sys::JNI_VERSION_1_6;
 }
\ No newline at end of file
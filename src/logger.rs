@@ -8,8 +8,33 @@ unsafe extern "C" { fn __android_log_print(prio: i32, tag: *const u8, fmt: *cons
 macro_rules! platform_print { ($level:expr, $tag:expr, $msg: expr) => { unsafe { __android_log_print(($level as i32 - 7) * -1, std::ffi::CString::new($tag).unwrap().as_ptr() as *const u8, std::ffi::CString::new($msg).unwrap().as_ptr() as *const u8); } }; }
 #[cfg(any(target_os = "windows", target_os = "linux"))]
 macro_rules! platform_print { ($level:expr, $tag:expr, $msg: expr) => { println!("[{}] [{}]: {}\n\0", $tag, $level, $msg) }; }
-pub struct SimpleLogger { pub file: OnceLock<Mutex<File>>, pub buffer: Mutex<VecDeque<(String, String)>>, pub is_levi_launcher: OnceLock<bool> }
-pub static LOGGER: SimpleLogger = SimpleLogger { file: OnceLock::new(), buffer: Mutex::new(VecDeque::new()), is_levi_launcher: OnceLock::new() };
+/// Bytes of log kept in memory for crash reports.
+const RECENT_BYTES: usize = 16 * 1024;
+
+/// The end of the log in a fixed ring buffer, so the crash handler can copy it out without
+/// allocating.
+pub struct RecentLog { data: [u8; RECENT_BYTES], end: usize, full: bool }
+
+impl RecentLog {
+    const fn new() -> Self { Self { data: [0; RECENT_BYTES], end: 0, full: false } }
+
+    fn push(&mut self, bytes: &[u8]) {
+        let bytes = &bytes[bytes.len().saturating_sub(RECENT_BYTES)..];
+        let first = bytes.len().min(RECENT_BYTES - self.end);
+        self.data[self.end..self.end + first].copy_from_slice(&bytes[..first]);
+        self.data[..bytes.len() - first].copy_from_slice(&bytes[first..]);
+        self.full |= self.end + bytes.len() >= RECENT_BYTES;
+        self.end = (self.end + bytes.len()) % RECENT_BYTES;
+    }
+
+    /// The kept bytes, oldest first.
+    pub fn slices(&self) -> (&[u8], &[u8]) {
+        if self.full { (&self.data[self.end..], &self.data[..self.end]) } else { (&[], &self.data[..self.end]) }
+    }
+}
+
+pub struct SimpleLogger { pub file: OnceLock<Mutex<File>>, pub buffer: Mutex<VecDeque<(String, String)>>, pub is_levi_launcher: OnceLock<bool>, pub recent: Mutex<RecentLog> }
+pub static LOGGER: SimpleLogger = SimpleLogger { file: OnceLock::new(), buffer: Mutex::new(VecDeque::new()), is_levi_launcher: OnceLock::new(), recent: Mutex::new(RecentLog::new()) };
 
 impl Log for SimpleLogger {
     fn enabled(&self, metadata: &Metadata) -> bool { metadata.level() <= Level::Debug }
@@ -24,6 +49,7 @@ impl Log for SimpleLogger {
         let msg = format!("[{timestamp}] [{}] {}\n", record.level(), msg_less);
 
         platform_print!(record.level(), tag, msg_less.clone());
+        if let Ok(mut recent) = self.recent.lock() { recent.push(msg.as_bytes()); }
 
         if let Some(file_mutex) = self.file.get() {
             if let Some(path) = config::log_path() {
@@ -59,6 +85,27 @@ pub fn init_log_file(is_levi_launcher: bool) {
                 platform_print!(Level::Debug, if is_levi_launcher { "LeviLogger" } else { "BuildLimitChanger" }, msg_less);
             }
         }
-        log::info!("\n    Logs: {}\n    Config: {}", path.display(), config_path().unwrap().display());
+        log::info!("\n    Logs: {}\n    Config: {}", path.display(), config_path().map_or_else(|| String::from("none"), |p| p.display().to_string()));
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recent_log_keeps_the_newest_bytes() {
+        let mut recent = RecentLog::new();
+        recent.push(b"first\n");
+        assert_eq!(recent.slices(), (&b""[..], &b"first\n"[..]));
+        let line = [b'x'; 1000];
+        for _ in 0..20 { recent.push(&line); }
+        recent.push(b"last\n");
+        let (old, new) = recent.slices();
+        let kept = [old, new].concat();
+        assert_eq!(kept.len(), RECENT_BYTES);
+        assert!(kept.ends_with(b"xlast\n"));
+        recent.push(&[b'y'; RECENT_BYTES + 10]);
+        let (old, new) = recent.slices();
+        assert!(old.iter().chain(new).all(|&b| b == b'y'));
     }
-}
\ No newline at end of file
+}
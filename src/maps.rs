@@ -0,0 +1,246 @@
+use crate::utils::TextMapRange;
+use std::{error::Error, fs};
+
+const ELF_MAGIC: &[u8; 4] = b"\x7fELF";
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PF_X: u32 = 1;
+const DT_NULL: usize = 0;
+const DT_STRTAB: usize = 5;
+const DT_SONAME: usize = 14;
+
+pub struct Mapping { pub start: usize, pub end: usize, pub perms: [u8; 4], pub offset: usize, pub path: String }
+
+impl Mapping {
+    pub fn readable(&self) -> bool { self.perms[0] == b'r' }
+    pub fn writable(&self) -> bool { self.perms[1] == b'w' }
+    pub fn executable(&self) -> bool { self.perms[2] == b'x' }
+
+    /// File name of the backing object, without the `memfd:` prefix or ` (deleted)` suffix
+    /// that in-memory loaders leave behind.
+    pub fn file_name(&self) -> &str {
+        let path = self.path.strip_suffix(" (deleted)").unwrap_or(&self.path);
+        let name = path.rsplit('/').next().unwrap_or(path);
+        name.strip_prefix("memfd:").unwrap_or(name)
+    }
+
+    /// Whether an ELF image could be mapped here and reading it cannot fault: anonymous and
+    /// `memfd:` memory or a regular file long enough for a header. Device mappings, `[vvar]`
+    /// and other pseudo mappings, and files shorter than the mapping raise SIGBUS/SIGSEGV.
+    fn may_hold_image(&self) -> bool {
+        let path = self.path.as_str();
+        if path.is_empty() || path.starts_with("[anon:") { return true; }
+        if path.starts_with('[') || path.starts_with("/dev/") { return false; }
+        // what in-memory loaders leave behind, there is no file to check but the memory is theirs
+        if path.starts_with("/memfd:") || path.ends_with(" (deleted)") { return true; }
+        fs::metadata(path).is_ok_and(|m| m.is_file() && m.len() >= 64)
+    }
+
+    fn matches(&self, target: &str) -> bool {
+        !self.path.is_empty() && (self.path == target || self.file_name() == target.rsplit('/').next().unwrap_or(target))
+    }
+}
+
+pub fn read_maps() -> Result<Vec<Mapping>, Box<dyn Error>> {
+    Ok(fs::read_to_string("/proc/self/maps")?.lines().filter_map(parse_line).collect())
+}
+
+fn parse_line(line: &str) -> Option<Mapping> {
+    let mut fields = line.splitn(6, ' ');
+    let (start, end) = fields.next()?.split_once('-')?;
+    let perms = fields.next()?.as_bytes().try_into().ok()?;
+    let offset = usize::from_str_radix(fields.next()?, 16).ok()?;
+    let (_dev, _inode) = (fields.next()?, fields.next()?);
+    Some(Mapping {
+        start: usize::from_str_radix(start, 16).ok()?,
+        end: usize::from_str_radix(end, 16).ok()?,
+        perms,
+        offset,
+        path: fields.next().unwrap_or("").trim_start().to_string(),
+    })
+}
+
+/// Checks that `[addr, addr + len)` is covered by contiguous mappings that all pass `check`.
+pub fn is_range_mapped(maps: &[Mapping], addr: usize, len: usize, check: impl Fn(&Mapping) -> bool) -> bool {
+    let Some(end) = addr.checked_add(len) else { return false };
+    let mut cursor = addr;
+    for map in maps.iter().skip_while(|m| m.end <= addr) {
+        if map.start > cursor || !check(map) { return false; }
+        cursor = map.end;
+        if cursor >= end { return true; }
+    }
+    false
+}
+
+/// Whether an executable mapping of `target` shows up by path, without touching any memory.
+pub fn is_mapped_by_path(target: &str) -> Result<bool, Box<dyn Error>> {
+    Ok(read_maps()?.iter().any(|m| m.matches(target) && m.executable()))
+}
+
+pub fn find_text_section(target: &str) -> Result<TextMapRange, Box<dyn Error>> {
+    let maps = read_maps()?;
+    find_text_by_path(&maps, target)
+        .or_else(|| find_text_by_elf_header(&maps, target))
+        .ok_or_else(|| format!("No executable mapping for {target} in /proc/self/maps").into())
+}
+
+fn find_text_by_path(maps: &[Mapping], target: &str) -> Option<TextMapRange> {
+    let first = maps.iter().position(|m| m.matches(target) && m.readable() && m.executable())?;
+    let mut end = maps[first].end;
+    for map in &maps[first + 1..] {
+        if map.start != end || !map.matches(target) || !(map.readable() && map.executable()) { break; }
+        end = map.end;
+    }
+    log::debug!("Found {} by path in /proc/self/maps: 0x{:X}-0x{:X}", target, maps[first].start, end);
+    Some(TextMapRange { start: maps[first].start, size: end - maps[first].start })
+}
+
+/// Fallback for images whose mapping carries no usable path: walk every ELF header that
+/// is mapped at file offset 0 and compare its `DT_SONAME` with the target.
+fn find_text_by_elf_header(maps: &[Mapping], target: &str) -> Option<TextMapRange> {
+    let target_name = target.rsplit('/').next().unwrap_or(target);
+    maps.iter()
+        .filter(|m| m.offset == 0 && m.readable() && m.end - m.start >= 64 && m.may_hold_image())
+        .filter(|m| unsafe { std::slice::from_raw_parts(m.start as *const u8, 4) } == ELF_MAGIC)
+        .find_map(|m| {
+            let image = unsafe { ElfImage::parse(maps, m.start)? };
+            if image.soname.as_deref() != Some(target_name) { return None; }
+            let (start, size) = image.text?;
+            if !is_range_mapped(maps, start, size, |m| m.readable() && m.executable()) {
+                log::warn!("{} text at 0x{:X} is not fully mapped r-x, skipping", target_name, start);
+                return None;
+            }
+            log::debug!("Found {} by ELF header at 0x{:X}: text 0x{:X}-0x{:X}", target_name, m.start, start, start + size);
+            Some(TextMapRange { start, size })
+        })
+}
+
+struct ElfImage { soname: Option<String>, text: Option<(usize, usize)> }
+
+impl ElfImage {
+    unsafe fn parse(maps: &[Mapping], base: usize) -> Option<Self> {
+        let readable = |addr: usize, len: usize| is_range_mapped(maps, addr, len, Mapping::readable);
+        let word = std::mem::size_of::<usize>();
+        // e_ident[EI_CLASS] must match our own pointer width, we only look at our own process
+        if *((base + 4) as *const u8) != if word == 8 { 2 } else { 1 } { return None; }
+        let (phoff, phentsize, phnum) = if word == 8 {
+            (read::<u64>(base + 0x20) as usize, read::<u16>(base + 0x36) as usize, read::<u16>(base + 0x38) as usize)
+        } else {
+            (read::<u32>(base + 0x1C) as usize, read::<u16>(base + 0x2A) as usize, read::<u16>(base + 0x2C) as usize)
+        };
+        if phnum == 0 || !readable(base + phoff, phentsize * phnum) { return None; }
+
+        let phdrs: Vec<(u32, u32, usize, usize)> = (0..phnum).map(|i| {
+            let p = base + phoff + i * phentsize;
+            if word == 8 {
+                (read::<u32>(p), read::<u32>(p + 4), read::<u64>(p + 16) as usize, read::<u64>(p + 40) as usize)
+            } else {
+                (read::<u32>(p), read::<u32>(p + 24), read::<u32>(p + 8) as usize, read::<u32>(p + 20) as usize)
+            }
+        }).collect();
+
+        let first_load = phdrs.iter().find(|p| p.0 == PT_LOAD)?.2;
+        let bias = base.wrapping_sub(first_load & !0xFFF);
+        let text = phdrs.iter()
+            .find(|p| p.0 == PT_LOAD && p.1 & PF_X != 0)
+            .map(|p| (bias.wrapping_add(p.2), p.3));
+        let soname = phdrs.iter()
+            .find(|p| p.0 == PT_DYNAMIC)
+            .and_then(|p| read_soname(bias.wrapping_add(p.2), bias, &readable));
+        Some(Self { soname, text })
+    }
+}
+
+unsafe fn read_soname(dynamic: usize, bias: usize, readable: &impl Fn(usize, usize) -> bool) -> Option<String> {
+    let word = std::mem::size_of::<usize>();
+    let (mut strtab, mut soname) = (None, None);
+    let mut entry = dynamic;
+    loop {
+        if !readable(entry, word * 2) { return None; }
+        let (tag, val) = (read::<usize>(entry), read::<usize>(entry + word));
+        match tag {
+            DT_NULL => break,
+            DT_STRTAB => strtab = Some(val),
+            DT_SONAME => soname = Some(val),
+            _ => {}
+        }
+        entry += word * 2;
+    }
+    let (strtab, soname) = (strtab?, soname?);
+    // glibc relocates d_ptr entries in place, bionic leaves them relative to the load bias
+    let strtab = if strtab < bias { bias.wrapping_add(strtab) } else { strtab };
+    let name = strtab.checked_add(soname)?;
+    if !readable(name, 1) { return None; }
+    let bytes = std::ffi::CStr::from_ptr(name as *const libc::c_char).to_bytes();
+    Some(String::from_utf8_lossy(bytes).into_owned())
+}
+
+unsafe fn read<T: Copy>(addr: usize) -> T { std::ptr::read_unaligned(addr as *const T) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAPS: &str = "\
+5600a000-5600c000 r--p 00000000 08:01 1234                       /usr/bin/bedrock_server
+5600c000-56010000 r-xp 00002000 08:01 1234                       /usr/bin/bedrock_server
+56010000-56011000 rw-p 00006000 08:01 1234                       /usr/bin/bedrock_server
+70000000-70004000 r-xp 00001000 00:01 99                         /memfd:libminecraftpe.so (deleted)
+7ffd0000-7ffd2000 rw-p 00000000 00:00 0                          [stack]
+7ffe0000-7ffe1000 rw-p 00000000 00:00 0
+not a mapping";
+
+    fn maps() -> Vec<Mapping> { MAPS.lines().filter_map(parse_line).collect() }
+
+    #[test]
+    fn lines_are_parsed() {
+        let maps = maps();
+        assert_eq!(maps.len(), 6);
+        let text = &maps[1];
+        assert_eq!((text.start, text.end, text.offset), (0x5600c000, 0x56010000, 0x2000));
+        assert!(text.readable() && text.executable() && !text.writable());
+        assert_eq!(text.path, "/usr/bin/bedrock_server");
+        assert!(maps[2].writable());
+        assert_eq!(maps[4].path, "[stack]");
+        assert_eq!(maps[5].path, "");
+        assert!(parse_line("5600a000 r--p 00000000 08:01 1234").is_none());
+        assert!(parse_line("5600a000-5600c000 r--p").is_none());
+    }
+
+    #[test]
+    fn file_names_drop_memfd_and_deleted() {
+        let maps = maps();
+        assert_eq!(maps[0].file_name(), "bedrock_server");
+        assert_eq!(maps[3].file_name(), "libminecraftpe.so");
+        assert!(maps[3].matches("libminecraftpe.so"));
+        assert!(maps[3].matches("/data/app/lib/arm64/libminecraftpe.so"));
+        assert!(!maps[5].matches(""));
+    }
+
+    #[test]
+    fn only_memory_that_can_hold_an_image_is_walked() {
+        let maps = maps();
+        assert!(maps[3].may_hold_image() && maps[5].may_hold_image());
+        assert!(!maps[4].may_hold_image());
+        for line in [
+            "7f000000-7f001000 r--s 00000000 00:06 12 /dev/kgsl-3d0",
+            "7ffe2000-7ffe4000 r--p 00000000 00:00 0 [vvar]",
+            "7f000000-7f001000 r--p 00000000 00:00 0 /nonexistent/libfoo.so",
+        ] {
+            assert!(!parse_line(line).unwrap().may_hold_image(), "{line}");
+        }
+        assert!(parse_line("7f000000-7f001000 r--p 00000000 00:00 0 [anon:dalvik-main space]").unwrap().may_hold_image());
+    }
+
+    #[test]
+    fn ranges_must_be_contiguous_and_pass_the_check() {
+        let maps = maps();
+        assert!(is_range_mapped(&maps, 0x5600b000, 0x2000, Mapping::readable));
+        assert!(is_range_mapped(&maps, 0x5600a000, 0x7000, Mapping::readable));
+        assert!(!is_range_mapped(&maps, 0x5600b000, 0x2000, Mapping::executable));
+        // runs into the gap after the writable mapping
+        assert!(!is_range_mapped(&maps, 0x56010000, 0x2000, Mapping::readable));
+        assert!(!is_range_mapped(&maps, 0x1000, 0x10, Mapping::readable));
+        assert!(!is_range_mapped(&maps, usize::MAX, 2, Mapping::readable));
+    }
+}
@@ -0,0 +1,176 @@
+use std::{error::Error, fmt};
+
+#[derive(Debug)]
+pub struct MemoryError { pub addr: usize, pub len: usize, pub access: &'static str }
+
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:X}..0x{:X} is not {}", self.addr, self.addr.wrapping_add(self.len), self.access)
+    }
+}
+
+impl Error for MemoryError {}
+
+#[derive(Clone, Copy)]
+struct Region { start: usize, end: usize, readable: bool, writable: bool }
+
+/// The memory map read once for an operation that checks several addresses, on Linux/Android
+/// every read of `/proc/self/maps` parses the whole file.
+pub struct Regions {
+    /// `None` where nothing is read up front: Windows asks per range, and without `/proc`
+    /// Linux falls back to mincore.
+    all: Option<Vec<Region>>,
+}
+
+impl Regions {
+    pub fn snapshot() -> Self {
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        return Self { all: crate::maps::read_maps().ok().map(|maps| maps.iter()
+            .map(|m| Region { start: m.start, end: m.end, readable: m.readable(), writable: m.writable() })
+            .collect()) };
+        #[cfg(target_os = "windows")]
+        Self { all: None }
+    }
+
+    fn within(&self, addr: usize, len: usize) -> Vec<Region> {
+        let end = addr.saturating_add(len);
+        match &self.all {
+            Some(all) => all.iter()
+                .filter(|r| r.end > addr && r.start < end)
+                .map(|r| Region { start: r.start.max(addr), end: r.end.min(end), ..*r })
+                .collect(),
+            None => query(addr, len),
+        }
+    }
+
+    fn covers(&self, addr: usize, len: usize, check: impl Fn(&Region) -> bool) -> bool {
+        let Some(end) = addr.checked_add(len) else { return false };
+        let mut cursor = addr;
+        for region in self.within(addr, len) {
+            if region.start > cursor || !check(&region) { return false; }
+            cursor = region.end;
+        }
+        cursor >= end
+    }
+
+    pub fn is_readable(&self, addr: usize, len: usize) -> bool { self.covers(addr, len, |r| r.readable) }
+    pub fn is_writable(&self, addr: usize, len: usize) -> bool { self.covers(addr, len, |r| r.readable && r.writable) }
+
+    pub fn read<T: Copy>(&self, addr: usize) -> Result<T, MemoryError> {
+        let len = std::mem::size_of::<T>();
+        if !self.is_readable(addr, len) { return Err(MemoryError { addr, len, access: "readable" }); }
+        Ok(unsafe { std::ptr::read_volatile(addr as *const T) })
+    }
+
+    pub fn read_bytes(&self, addr: usize, buf: &mut [u8]) -> Result<(), MemoryError> {
+        if !self.is_readable(addr, buf.len()) { return Err(MemoryError { addr, len: buf.len(), access: "readable" }); }
+        unsafe { std::ptr::copy_nonoverlapping(addr as *const u8, buf.as_mut_ptr(), buf.len()) };
+        Ok(())
+    }
+
+    pub fn write<T: Copy>(&self, addr: usize, value: T) -> Result<(), MemoryError> {
+        let len = std::mem::size_of::<T>();
+        if !self.is_writable(addr, len) { return Err(MemoryError { addr, len, access: "writable" }); }
+        unsafe { std::ptr::write_volatile(addr as *mut T, value) };
+        Ok(())
+    }
+
+    /// Splits `[start, start + size)` into the sub ranges that are actually mapped readable.
+    pub fn readable_ranges(&self, start: usize, size: usize) -> Vec<(usize, usize)> {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for region in self.within(start, size).into_iter().filter(|r| r.readable) {
+            match ranges.last_mut() {
+                Some(last) if last.0 + last.1 == region.start => last.1 += region.end - region.start,
+                _ => ranges.push((region.start, region.end - region.start)),
+            }
+        }
+        ranges
+    }
+}
+
+pub fn is_readable(addr: usize, len: usize) -> bool { Regions::snapshot().is_readable(addr, len) }
+
+pub fn read_bytes(addr: usize, buf: &mut [u8]) -> Result<(), MemoryError> { Regions::snapshot().read_bytes(addr, buf) }
+
+pub fn readable_ranges(start: usize, size: usize) -> Vec<(usize, usize)> { Regions::snapshot().readable_ranges(start, size) }
+
+// without /proc access mincore can still tell us whether the pages exist at all, but not
+// their protection, so writes are refused rather than risking a fault on read-only pages
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn query(addr: usize, len: usize) -> Vec<Region> {
+    let end = addr.saturating_add(len);
+    unsafe {
+        let page = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+        let aligned = addr & !(page - 1);
+        let mut vec = vec![0u8; (end - aligned).div_ceil(page)];
+        let mapped = libc::mincore(aligned as *mut libc::c_void, end - aligned, vec.as_mut_ptr() as _) == 0;
+        if mapped { vec![Region { start: addr, end, readable: true, writable: false }] } else { Vec::new() }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn query(addr: usize, len: usize) -> Vec<Region> {
+    use windows_sys::Win32::System::Memory::*;
+    let end = addr.saturating_add(len);
+    let mut regions = Vec::new();
+    let mut cursor = addr;
+    while cursor < end {
+        let mut info = unsafe { std::mem::zeroed::<MEMORY_BASIC_INFORMATION>() };
+        if unsafe { VirtualQuery(cursor as *const _, &mut info, std::mem::size_of::<MEMORY_BASIC_INFORMATION>()) } == 0 { break; }
+        let region_end = (info.BaseAddress as usize).saturating_add(info.RegionSize);
+        let usable = info.State == MEM_COMMIT && info.Protect & (PAGE_GUARD | PAGE_NOACCESS) == 0;
+        let readable = usable && info.Protect & (PAGE_READONLY | PAGE_READWRITE | PAGE_WRITECOPY | PAGE_EXECUTE_READ | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY) != 0;
+        let writable = usable && info.Protect & (PAGE_READWRITE | PAGE_WRITECOPY | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY) != 0;
+        regions.push(Region { start: cursor, end: region_end.min(end), readable, writable });
+        cursor = region_end;
+    }
+    regions
+}
+
+/// Overwrites code at `addr`, making the pages writable only for the duration of the copy.
+pub fn patch_code(addr: usize, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+    if !is_readable(addr, bytes.len()) { return Err(MemoryError { addr, len: bytes.len(), access: "readable" }.into()); }
+    #[cfg(any(target_os = "android", target_os = "linux"))] unsafe {
+        let page = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+        let start = addr & !(page - 1);
+        let len = addr + bytes.len() - start;
+        if libc::mprotect(start as *mut _, len, libc::PROT_READ | libc::PROT_WRITE | libc::PROT_EXEC) != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), addr as *mut u8, bytes.len());
+        libc::mprotect(start as *mut _, len, libc::PROT_READ | libc::PROT_EXEC);
+        #[cfg(target_arch = "aarch64")] {
+            unsafe extern "C" { fn __clear_cache(start: *mut libc::c_char, end: *mut libc::c_char); }
+            __clear_cache(addr as *mut _, (addr + bytes.len()) as *mut _);
+        }
+    }
+    #[cfg(target_os = "windows")] unsafe {
+        use windows_sys::Win32::System::{Diagnostics::Debug::FlushInstructionCache, Memory::{VirtualProtect, PAGE_EXECUTE_READWRITE}, Threading::GetCurrentProcess};
+        let mut old = 0;
+        if VirtualProtect(addr as *const _, bytes.len(), PAGE_EXECUTE_READWRITE, &mut old) == 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), addr as *mut u8, bytes.len());
+        VirtualProtect(addr as *const _, bytes.len(), old, &mut old);
+        FlushInstructionCache(GetCurrentProcess(), addr as *const _, bytes.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_snapshot_serves_several_checks() {
+        let regions = Regions::snapshot();
+        let mut value = Box::new(0x1234_5678u32);
+        let addr = &mut *value as *mut u32 as usize;
+        assert_eq!(regions.read::<u32>(addr).unwrap(), 0x1234_5678);
+        regions.write(addr, 0x8765_4321u32).unwrap();
+        assert_eq!(*value, 0x8765_4321);
+        assert!(regions.read::<u32>(0).is_err());
+        assert!(!regions.is_readable(usize::MAX - 1, 4));
+        assert_eq!(regions.readable_ranges(addr, 4), [(addr, 4)]);
+    }
+}
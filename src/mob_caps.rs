@@ -0,0 +1,150 @@
+//! Mob caps: the immediates the scan anchors on are the game's mob cap values, stored as floats
+//! in the hooked function's code. They are patched in place at startup from `mob_caps`.
+use std::sync::Mutex;
+
+use crate::{config, memory, scan};
+
+/// Name of the cap the scan itself found, the others are numbered in address order.
+const ANCHOR: &str = "water";
+
+pub struct CapSite { pub name: String, pub addr: usize, pub value: f32 }
+
+/// Address and original bytes of every patched cap, for `restore`.
+static PATCHED: Mutex<Vec<(usize, Vec<u8>)>> = Mutex::new(Vec::new());
+
+/// Offset and size of the immediate in the instruction at the start of `code`, with its value.
+fn immediate(code: &[u8]) -> Option<(usize, usize, f32)> {
+    #[cfg(target_arch = "aarch64")] {
+        // movz/movk with a 16 bit immediate in bits 5..21, the high half of the float
+        let word = u32::from_le_bytes(code.get(..4)?.try_into().ok()?);
+        Some((0, 4, f32::from_bits(((word >> 5) & 0xFFFF) << 16)))
+    }
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))] {
+        use iced_x86::{Decoder, OpKind};
+        let bitness = if cfg!(target_arch = "x86_64") { 64 } else { 32 };
+        let instruction = Decoder::new(bitness, &code[..code.len().min(15)], iced_x86::DecoderOptions::NONE).decode();
+        let size = match instruction.op1_kind() {
+            OpKind::Immediate32 | OpKind::Immediate32to64 => 4,
+            OpKind::Immediate64 => 8,
+            _ => return None,
+        };
+        Some((instruction.len() - size, size, f32::from_bits(instruction.try_immediate(1).ok()? as u32)))
+    }
+}
+
+/// The instruction bytes at the start of `code` with the float replaced by `value`.
+fn encode(code: &[u8], value: f32) -> Result<Vec<u8>, String> {
+    if !value.is_finite() || value < 0.0 { return Err(String::from("not a number of 0 or more")); }
+    if value > config::MAX_MOB_CAP { return Err(format!("above the maximum of {}", config::MAX_MOB_CAP)); }
+    let (offset, size, _) = immediate(code).ok_or("not a mov with an immediate")?;
+    #[cfg(target_arch = "aarch64")] {
+        let bits = value.to_bits();
+        if bits & 0xFFFF != 0 { return Err(String::from("arm64 code only holds the high half of the float, e.g. 36, 37 or 36.5 work but 36.1 does not")); }
+        let word = u32::from_le_bytes(code[offset..offset + size].try_into().unwrap());
+        Ok(((word & !(0xFFFF << 5)) | ((bits >> 16) << 5)).to_le_bytes().to_vec())
+    }
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))] {
+        let mut bytes = code[..offset + size].to_vec();
+        bytes[offset..].copy_from_slice(&(value.to_bits() as u64).to_le_bytes()[..size]);
+        Ok(bytes)
+    }
+}
+
+/// Cap instructions in `code`, named after their relation to the scan's `anchor`.
+pub fn sites(code: &[u8], anchor: usize) -> Vec<CapSite> {
+    let base = code.as_ptr() as usize;
+    let mut number = 0;
+    scan::cap_sites(code).into_iter().filter_map(|addr| {
+        let (_, _, value) = immediate(&code[addr - base..])?;
+        let name = if addr == anchor { ANCHOR.to_string() } else { number += 1; format!("cap_{number}") };
+        Some(CapSite { name, addr, value })
+    }).collect()
+}
+
+/// Patches the configured caps found in the hooked function `function..end`.
+pub fn apply(function: usize, end: usize, anchor: usize) {
+    let config = config::load();
+    if !config.settings.enabled { return; }
+    if !memory::is_readable(function, end - function) { return log::warn!("Hooked function is not readable, mob caps left alone"); }
+    let code = unsafe { std::slice::from_raw_parts(function as *const u8, end - function) };
+    let sites = sites(code, anchor);
+    for site in &sites { log::info!("Mob cap {} = {} at 0x{:X}", site.name, site.value, site.addr); }
+    let mut configured: Vec<(&String, &f32)> = config.mob_caps.iter().collect();
+    configured.sort_by_key(|(name, _)| name.as_str());
+    for (name, &value) in configured {
+        let Some(site) = sites.iter().find(|s| s.name == *name) else {
+            let known: Vec<&str> = sites.iter().map(|s| s.name.as_str()).collect();
+            log::warn!("Unknown mob cap {name}, found: {}", known.join(", "));
+            continue;
+        };
+        if value == site.value { continue; }
+        let code = &code[site.addr - function..];
+        let bytes = match encode(code, value) {
+            Ok(bytes) => bytes,
+            Err(reason) => { log::warn!("Mob cap {} Config {} rejected: {}, keeping {}", name, value, reason, site.value); continue; }
+        };
+        log::info!("Changing Mob cap {}: {} to {}", name, site.value, value);
+        if config.settings.dry_run { log::info!("Dry run, not writing: Mob cap {} keeps {}", name, site.value); continue; }
+        if let Err(e) = patch(site.addr, &bytes) { log::warn!("Leaving mob cap {name} untouched: {e}"); }
+    }
+}
+
+/// Writes `bytes` at `addr`, keeping what was there for `restore`.
+fn patch(addr: usize, bytes: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let original = unsafe { std::slice::from_raw_parts(addr as *const u8, bytes.len()) }.to_vec();
+    memory::patch_code(addr, bytes)?;
+    PATCHED.lock().unwrap().push((addr, original));
+    Ok(())
+}
+
+/// Whether any cap is currently patched.
+pub fn is_patched() -> bool { PATCHED.lock().is_ok_and(|p| !p.is_empty()) }
+
+/// Writes the game's own caps back, called when the hook is removed or fails to go in.
+pub fn restore() {
+    for (addr, original) in PATCHED.lock().unwrap().drain(..) {
+        match memory::patch_code(addr, &original) {
+            Ok(()) => log::info!("Restored mob cap at 0x{addr:X}"),
+            Err(e) => log::warn!("Cannot restore mob cap at 0x{addr:X}: {e}"),
+        }
+    }
+}
+
+#[cfg(all(test, any(target_arch = "x86_64", target_arch = "x86")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caps_are_reencoded_in_place() {
+        // mov eax, 36.0
+        let code = [0xB8, 0x00, 0x00, 0x10, 0x42, 0xC3];
+        assert_eq!(immediate(&code), Some((1, 4, 36.0)));
+        assert_eq!(encode(&code, 50.0), Ok(vec![0xB8, 0x00, 0x00, 0x48, 0x42]));
+        // mov dword ptr [rbx+8], 2.0
+        let code = [0xC7, 0x43, 0x08, 0x00, 0x00, 0x00, 0x40];
+        assert_eq!(immediate(&code), Some((3, 4, 2.0)));
+        assert_eq!(encode(&code, 4.0).unwrap()[3..], [0x00, 0x00, 0x80, 0x40]);
+        assert!(encode(&code, -1.0).is_err());
+        assert!(encode(&code, f32::NAN).is_err());
+        assert!(encode(&code, config::MAX_MOB_CAP + 1.0).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn restore_puts_the_original_caps_back() {
+        // patch_code flips page protections, so the code gets a page of its own
+        let page = unsafe { libc::mmap(std::ptr::null_mut(), 4096, libc::PROT_READ | libc::PROT_EXEC, libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0) };
+        assert_ne!(page, libc::MAP_FAILED);
+        let addr = page as usize;
+        // mov eax, 36.0
+        let code = [0xB8, 0x00, 0x00, 0x10, 0x42, 0xC3];
+        memory::patch_code(addr, &code).unwrap();
+        patch(addr, &encode(&code, 50.0).unwrap()).unwrap();
+        assert!(is_patched());
+        assert_eq!(unsafe { std::slice::from_raw_parts(addr as *const u8, 5) }, [0xB8, 0x00, 0x00, 0x48, 0x42]);
+        restore();
+        assert!(!is_patched());
+        assert_eq!(unsafe { std::slice::from_raw_parts(addr as *const u8, 6) }, code);
+        unsafe { libc::munmap(page, 4096) };
+    }
+}
@@ -0,0 +1,377 @@
+//! Finds the function that sets dimension height ranges. The text section is split into chunks
+//! that worker threads reduce to the few instructions the search cares about, then one pass over
+//! those events in address order gives exactly what a single threaded walk would.
+use std::ops::Range;
+
+/// Chunks smaller than this are not worth a thread.
+const MIN_CHUNK_LEN: usize = 1 << 20;
+/// x86 instructions of a chunk that starts mid instruction line up with the previous chunk's
+/// within a few bytes, instruction starts in this many bytes are kept to find where.
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+const SYNC_WINDOW: usize = 4096;
+
+/// A forward walk this many bytes before a prefilter hit lines up with the real instructions.
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+const CONFIRM_WINDOW: usize = 64;
+/// Bytes decoded on each side of the cap when looking for its function, grown until one is found.
+const FUNCTION_WINDOW: usize = 64 * 1024;
+/// Past this the window is given up on in favour of a full parallel scan.
+const MAX_FUNCTION_WINDOW: usize = 16 << 20;
+
+#[cfg(target_arch = "aarch64")]
+const MASKS: [u32; 3] = [0xFFFF_FC1F, 0xFFFF_FFE0, 0xFF00_0000];
+#[cfg(target_arch = "aarch64")]
+const PATTERNS: [u32; 3] = [0xD65F_0000, 0x52A8_4200, 0xD100_0000];
+#[cfg(target_arch = "aarch64")]
+const LMAO: u32 = 0xF2E8_4200;
+
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+const TARGET_IMMEDIATE: u64 = 0x42100000;
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+const TARGET_IMMEDIATE2: u64 = 0x40000000;
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+const BITNESS: u32 = if cfg!(target_arch = "x86_64") { 64 } else { 32 };
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Event {
+    Ret,
+    /// An instruction that starts a function when it directly follows a `ret`.
+    Start,
+    /// A candidate water mob cap immediate.
+    Cap(u64),
+    /// Windows x64 `mov`s are both: a function start after a `ret`, a cap candidate otherwise.
+    #[cfg_attr(not(all(target_os = "windows", target_arch = "x86_64")), allow(dead_code))]
+    StartOrCap(u64),
+}
+
+/// Candidates kept in `ScanResult::candidates`, a text section can have thousands.
+const MAX_CANDIDATES: usize = 32;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ScanResult {
+    /// The water mob cap address together with its distance to the previous candidate,
+    /// so results from separately scanned ranges can be compared.
+    pub cap: Option<(usize, usize)>,
+    /// Every candidate that had a previous one, with that distance, for bug reports. Only the
+    /// `MAX_CANDIDATES` closest are kept, in address order.
+    pub candidates: Vec<(usize, usize)>,
+    pub fn_starts: Vec<usize>,
+}
+
+#[derive(Default)]
+struct Chunk {
+    events: Vec<(usize, Event)>,
+    /// Offsets of the instructions starting inside the sync window.
+    #[cfg_attr(target_arch = "aarch64", allow(dead_code))]
+    window: Vec<usize>,
+    /// Offset where decoding stopped, past the last instruction.
+    #[cfg_attr(target_arch = "aarch64", allow(dead_code))]
+    end: usize,
+}
+
+/// Decodes `data` from offset `from` until `stop` returns true for the next instruction offset.
+/// Every `Start` in the first `window` bytes is kept, after that only the first one after each
+/// `ret`, which is all the state machine can use.
+fn tokenize(data: &[u8], from: usize, window: usize, stop: impl Fn(usize) -> bool) -> Chunk {
+    let base = data.as_ptr() as usize;
+    let mut chunk = Chunk::default();
+    let mut pending = true;
+    let mut past_window = false;
+    let start = |chunk: &mut Chunk, offset: usize, event: Event, pending: &mut bool| {
+        if offset < from + window || *pending { chunk.events.push((base + offset, event)); *pending = false; true } else { false }
+    };
+
+    #[cfg(target_arch = "aarch64")] {
+        let mut offset = from;
+        while offset + 4 <= data.len() && !stop(offset) {
+            if !past_window && offset >= from + window { past_window = true; pending = true; }
+            let instr = u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+            if (instr & MASKS[0]) == PATTERNS[0] {
+                chunk.events.push((base + offset, Event::Ret));
+                pending = true;
+            } else if (instr & MASKS[2]) == PATTERNS[2] {
+                start(&mut chunk, offset, Event::Start, &mut pending);
+            } else if matches!(instr & MASKS[1], m if m == PATTERNS[1] || m == LMAO) {
+                chunk.events.push((base + offset, Event::Cap(0)));
+            }
+            if offset < from + window { chunk.window.push(offset); }
+            offset += 4;
+        }
+        chunk.end = offset;
+    }
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))] {
+        use iced_x86::{Decoder, Instruction, Mnemonic};
+        let mut decoder = Decoder::with_ip(BITNESS, &data[from..], (base + from) as u64, iced_x86::DecoderOptions::NO_INVALID_CHECK);
+        let mut instruction = Instruction::default();
+        while decoder.can_decode() {
+            let offset = decoder.ip() as usize - base;
+            if stop(offset) { break; }
+            if !past_window && offset >= from + window { past_window = true; pending = true; }
+            if offset < from + window { chunk.window.push(offset); }
+            decoder.decode_out(&mut instruction);
+            match instruction.mnemonic() {
+                Mnemonic::Ret => { chunk.events.push((base + offset, Event::Ret)); pending = true; }
+                Mnemonic::Mov => {
+                    let current = instruction.try_immediate(1).unwrap_or(0);
+                    let is_target = matches!(current, TARGET_IMMEDIATE | TARGET_IMMEDIATE2);
+                    #[cfg(all(target_os = "windows", target_arch = "x86_64"))]
+                    if start(&mut chunk, offset, if is_target { Event::StartOrCap(current) } else { Event::Start }, &mut pending) { continue; }
+                    if is_target { chunk.events.push((base + offset, Event::Cap(current))); }
+                }
+                #[cfg(any(target_os = "android", target_os = "linux", all(target_os = "windows", target_arch = "x86")))]
+                Mnemonic::Push => { start(&mut chunk, offset, Event::Start, &mut pending); }
+                _ => {}
+            }
+        }
+        chunk.end = decoder.ip() as usize - base;
+    }
+    chunk
+}
+
+/// Replays the events in address order, this is the whole search apart from decoding.
+fn run(events: impl IntoIterator<Item = (usize, Event)>) -> ScanResult {
+    let mut seen_ret = false;
+    let mut fn_starts = Vec::new();
+    let mut last_cap: Option<usize> = None;
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+    let mut last_target: u64 = 0;
+    let mut candidates = Vec::new();
+    for (addr, event) in events {
+        let immediate = match event {
+            Event::Ret => { seen_ret = true; continue; }
+            Event::Start | Event::StartOrCap(_) if seen_ret => { fn_starts.push(addr); seen_ret = false; continue; }
+            Event::Start => continue,
+            Event::Cap(immediate) | Event::StartOrCap(immediate) => immediate,
+        };
+        // x86 only counts a candidate when the immediate differs from the previous one
+        #[cfg(any(target_arch = "x86_64", target_arch = "x86"))] {
+            if immediate == last_target { continue; }
+            last_target = immediate;
+        }
+        let _ = immediate;
+        if let Some(prev) = last_cap { candidates.push((addr, addr.wrapping_sub(prev))); }
+        last_cap = Some(addr);
+    }
+    // the first of equally close candidates wins
+    let cap = candidates.iter().copied().reduce(|best, c| if c.1 < best.1 { c } else { best });
+    candidates.sort_by_key(|&(addr, dist)| (dist, addr));
+    candidates.truncate(MAX_CANDIDATES);
+    candidates.sort_unstable_by_key(|&(addr, _)| addr);
+    ScanResult { cap, candidates, fn_starts }
+}
+
+/// Single threaded reference walk over the whole of `data`.
+#[cfg(test)]
+fn scan_sequential(data: &[u8]) -> ScanResult {
+    run(tokenize(data, 0, 0, |offset| offset >= data.len()).events)
+}
+
+fn chunks(len: usize, chunk_len: usize) -> Vec<Range<usize>> {
+    let chunk_len = chunk_len.max(4).next_multiple_of(4);
+    (0..len).step_by(chunk_len).map(|start| start..(start + chunk_len).min(len)).collect()
+}
+
+/// Orders the chunk events, on x86 decoding on from each chunk's end until it meets an
+/// instruction start of the next chunk, whose events before that point are misaligned.
+fn stitch(data: &[u8], ranges: &[Range<usize>], chunks: Vec<Chunk>) -> Vec<(usize, Event)> {
+    #[cfg(target_arch = "aarch64")] {
+        let _ = (data, ranges);
+        chunks.into_iter().flat_map(|c| c.events).collect()
+    }
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))] {
+        let base = data.as_ptr() as usize;
+        let mut events = Vec::new();
+        let mut position = 0;
+        for (range, chunk) in ranges.iter().zip(chunks) {
+            let window_end = range.start + SYNC_WINDOW;
+            let synced = |offset: usize| chunk.window.binary_search(&offset).is_ok();
+            let catch_up = tokenize(data, position, 0, |offset| offset >= range.start && (synced(offset) || offset >= window_end));
+            events.extend(catch_up.events);
+            position = catch_up.end;
+            if synced(position) {
+                events.extend(chunk.events.into_iter().filter(|&(addr, _)| addr >= base + position));
+                position = chunk.end;
+            } else {
+                // never lined up, walk the chunk again from the aligned position
+                let rest = tokenize(data, position, 0, |offset| offset >= range.end);
+                events.extend(rest.events);
+                position = rest.end;
+            }
+        }
+        events
+    }
+}
+
+fn scan_chunked(data: &[u8], chunk_len: usize) -> ScanResult {
+    let ranges = chunks(data.len(), chunk_len);
+    #[cfg(target_arch = "aarch64")] let window = 0;
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))] let window = SYNC_WINDOW;
+    let tokenized: Vec<Chunk> = std::thread::scope(|scope| {
+        let workers: Vec<_> = ranges.iter().cloned()
+            .map(|range| scope.spawn(move || tokenize(data, range.start, window, |offset| offset >= range.end)))
+            .collect();
+        workers.into_iter().map(|w| w.join().unwrap_or_default()).collect()
+    });
+    run(stitch(data, &ranges, tokenized))
+}
+
+/// Decodes every instruction of `data` on all available cores.
+fn scan_full(data: &[u8]) -> ScanResult {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    scan_chunked(data, (data.len() / threads).max(MIN_CHUNK_LEN))
+}
+
+/// Cap candidates found by searching for the immediate's bytes (SIMD accelerated by memchr)
+/// and decoding only around each hit.
+fn prefilter_caps(data: &[u8]) -> Vec<(usize, Event)> {
+    let base = data.as_ptr() as usize;
+    #[cfg(target_arch = "aarch64")] {
+        // movz/movk words end in the same 3 bytes whatever the register, which is in the first one
+        let needles = [&PATTERNS[1].to_le_bytes()[1..], &LMAO.to_le_bytes()[1..]];
+        let mut caps: Vec<(usize, Event)> = needles.iter()
+            .flat_map(|needle| memchr::memmem::find_iter(data, needle))
+            .filter_map(|hit| hit.checked_sub(1))
+            .filter(|offset| offset.is_multiple_of(4))
+            .filter(|&offset| {
+                let masked = u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) & MASKS[1];
+                masked == PATTERNS[1] || masked == LMAO
+            })
+            .map(|offset| (base + offset, Event::Cap(0)))
+            .collect();
+        caps.sort_unstable_by_key(|&(addr, _)| addr);
+        caps
+    }
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))] {
+        use iced_x86::{Decoder, Mnemonic};
+        let needles = [(TARGET_IMMEDIATE as u32).to_le_bytes(), (TARGET_IMMEDIATE2 as u32).to_le_bytes()];
+        let mut hits: Vec<usize> = needles.iter().flat_map(|needle| memchr::memmem::find_iter(data, needle)).collect();
+        hits.sort_unstable();
+        let mut caps: Vec<(usize, Event)> = hits.into_iter().filter_map(|hit| {
+            let from = hit.saturating_sub(CONFIRM_WINDOW);
+            let mut decoder = Decoder::with_ip(BITNESS, &data[from..], (base + from) as u64, iced_x86::DecoderOptions::NO_INVALID_CHECK);
+            let instruction = decoder.iter().find(|i| i.next_ip() as usize > base + hit)?;
+            let immediate = instruction.try_immediate(1).unwrap_or(0);
+            let confirmed = instruction.mnemonic() == Mnemonic::Mov && matches!(immediate, TARGET_IMMEDIATE | TARGET_IMMEDIATE2);
+            confirmed.then(|| (instruction.ip() as usize, Event::Cap(immediate)))
+        }).collect();
+        caps.dedup_by_key(|&mut (addr, _)| addr);
+        caps
+    }
+}
+
+/// Addresses of every cap candidate instruction in `code`, e.g. the hooked function.
+pub fn cap_sites(code: &[u8]) -> Vec<usize> {
+    prefilter_caps(code).into_iter().map(|(addr, _)| addr).collect()
+}
+
+/// Function starts around `cap`, from a window that doubles until one before the cap shows up.
+fn function_starts_around(data: &[u8], cap: usize) -> Option<Vec<usize>> {
+    let base = data.as_ptr() as usize;
+    let cap_offset = cap - base;
+    let mut window = FUNCTION_WINDOW;
+    while window <= MAX_FUNCTION_WINDOW {
+        let from = cap_offset.saturating_sub(window) / 4 * 4;
+        let to = (cap_offset + window).min(data.len());
+        let mut events = tokenize(data, from, 0, |offset| offset >= to).events;
+        // x86 instructions right after an arbitrary start are likely misaligned
+        #[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+        if from > 0 { events.retain(|&(addr, _)| addr >= base + from + CONFIRM_WINDOW); }
+        let fn_starts = run(events).fn_starts;
+        if from == 0 || fn_starts.iter().any(|&start| start < cap) { return Some(fn_starts); }
+        window *= 4;
+    }
+    None
+}
+
+/// Finds the cap through the byte prefilter and decodes only around it, falling back to
+/// decoding everything if its function start is too far away.
+pub fn scan(data: &[u8]) -> ScanResult {
+    let ScanResult { cap, candidates, .. } = run(prefilter_caps(data));
+    let Some((cap, dist)) = cap else { return ScanResult { candidates, ..ScanResult::default() } };
+    match function_starts_around(data, cap) {
+        Some(fn_starts) => ScanResult { cap: Some((cap, dist)), candidates, fn_starts },
+        None => {
+            log::warn!("No function start near 0x{:X}, decoding the whole range", cap);
+            scan_full(data)
+        }
+    }
+}
+
+#[cfg(all(test, any(target_arch = "x86_64", target_arch = "x86")))]
+mod tests {
+    use super::*;
+
+    /// Random bytes with rets, pushes and movs sprinkled in, a target immediate every
+    /// `cap_every` pieces on average.
+    fn fixture_with(len: usize, mut seed: u64, cap_every: u64) -> Vec<u8> {
+        let mut data = Vec::with_capacity(len + 16);
+        while data.len() < len {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            match (seed >> 59) as u8 {
+                0 => data.push(0xC3),
+                1 => data.push(0x55),
+                2 | 3 if !(seed >> 20).is_multiple_of(cap_every) => data.extend_from_slice(&[0x89, 0xC8]),
+                2 => data.extend_from_slice(&[0xB8, 0x00, 0x00, 0x10, 0x42]),
+                3 => data.extend_from_slice(&[0xB9, 0x00, 0x00, 0x00, 0x40]),
+                _ => data.extend_from_slice(&(seed >> 8).to_le_bytes()[..(seed % 7) as usize + 1]),
+            }
+        }
+        data
+    }
+
+    fn fixture(len: usize, seed: u64) -> Vec<u8> { fixture_with(len, seed, 1) }
+
+    /// What init hooks: the cap and the closest function start before it.
+    fn function(result: &ScanResult) -> (Option<(usize, usize)>, Option<usize>) {
+        let cap = result.cap;
+        (cap, cap.and_then(|(addr, _)| result.fn_starts.iter().copied().filter(|&s| s < addr).max()))
+    }
+
+    #[test]
+    fn chunked_scan_matches_sequential() {
+        for seed in 0..4 {
+            let data = fixture(64 * 1024, seed);
+            let expected = scan_sequential(&data);
+            assert!(expected.cap.is_some() && !expected.fn_starts.is_empty());
+            for chunk_len in [61, 1000, 4096, 10_000, data.len()] {
+                assert_eq!(scan_chunked(&data, chunk_len), expected, "seed {seed} chunk {chunk_len}");
+            }
+        }
+    }
+
+    #[test]
+    fn closest_pair_wins() {
+        let mut data = vec![0xC3, 0x55, 0xB8, 0x00, 0x00, 0x10, 0x42, 0x90];
+        data.extend_from_slice(&[0xB9, 0x00, 0x00, 0x00, 0x40, 0xB8, 0x00, 0x00, 0x10, 0x42]);
+        let base = data.as_ptr() as usize;
+        let result = scan_sequential(&data);
+        assert_eq!(result.fn_starts, [base + 1]);
+        assert_eq!(result.cap, Some((base + 13, 5)));
+        assert_eq!(result.candidates, [(base + 8, 6), (base + 13, 5)]);
+    }
+
+    #[test]
+    fn prefilter_finds_the_same_function() {
+        for (seed, cap_every) in [(0, 1), (1, 1), (2, 50), (3, 400), (4, 400)] {
+            let data = fixture_with(512 * 1024, seed, cap_every);
+            let (result, expected) = (scan(&data), scan_sequential(&data));
+            assert_eq!(function(&result), function(&expected), "seed {seed}");
+            assert_eq!(result.candidates, expected.candidates, "seed {seed}");
+            assert!(result.candidates.len() <= MAX_CANDIDATES && result.cap.is_none_or(|cap| result.candidates.contains(&cap)));
+        }
+    }
+
+    /// `cargo test --release -- --ignored --nocapture prefilter_speedup`
+    #[test]
+    #[ignore]
+    fn prefilter_speedup() {
+        let data = fixture_with(256 << 20, 7, 2000);
+        let time = |f: &dyn Fn() -> ScanResult| { let start = std::time::Instant::now(); let r = f(); (start.elapsed(), r) };
+        let (sequential, expected) = time(&|| scan_sequential(&data));
+        let (full, _) = time(&|| scan_full(&data));
+        let (prefiltered, result) = time(&|| scan(&data));
+        assert_eq!(function(&result), function(&expected));
+        println!("256 MiB: sequential {sequential:?}, parallel {full:?}, prefiltered {prefiltered:?} ({:.1}x over sequential)",
+            sequential.as_secs_f64() / prefiltered.as_secs_f64());
+    }
+}
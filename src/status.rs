@@ -0,0 +1,24 @@
+//! One line init reports for `blc-launcher`, written to the pipe it passes in `BLC_STATUS_FD`.
+//! The lines are `hooked <address>`, `cancelled`, `disabled` or `failed <reason>`.
+use std::io::Write;
+
+fn status_fd() -> Option<libc::c_int> {
+    crate::config::override_value("STATUS_FD").and_then(|o| o.value.parse().ok())
+}
+
+/// Keeps the pipe and its number away from processes the server starts: they would hold the
+/// launcher's read end open past the server's exit, or write into whatever fd has that number.
+/// The overrides are read before the variable goes, so `report` still finds the fd.
+pub fn init() {
+    let Some(fd) = status_fd() else { return };
+    std::env::remove_var("BLC_STATUS_FD");
+    unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+}
+
+/// The launcher's read end sees EOF when the server exits, so the fd is never closed here.
+pub fn report(status: &str) {
+    let Some(fd) = status_fd() else { return };
+    let line = format!("{}\n", status.replace('\n', " "));
+    let file = std::mem::ManuallyDrop::new(unsafe { <std::fs::File as std::os::fd::FromRawFd>::from_raw_fd(fd) });
+    if let Err(e) = (&*file).write_all(line.as_bytes()) { log::warn!("Cannot report status to fd {fd}: {e}"); }
+}
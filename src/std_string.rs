@@ -0,0 +1,161 @@
+use crate::memory::{MemoryError, Regions};
+
+/// Longest heap string we follow the pointer for, no dimension name comes close.
+const MAX_LONG_LEN: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    /// libc++ (Android, Linux): the lowest bit of the first byte marks long mode, short strings
+    /// keep `size << 1` in that byte followed by the characters, long ones are `{ cap | 1, size, data }`.
+    #[cfg_attr(target_os = "windows", allow(dead_code))]
+    LibCxx,
+    /// MSVC: `{ union { char buf[16]; char* ptr; }, size, capacity }`, inline while capacity < 16.
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+    Msvc,
+}
+
+impl Layout {
+    #[cfg(not(target_os = "windows"))]
+    pub const NATIVE: Layout = Layout::LibCxx;
+    #[cfg(target_os = "windows")]
+    pub const NATIVE: Layout = Layout::Msvc;
+
+    fn size(self, word: usize) -> usize {
+        match self { Layout::LibCxx => 3 * word, Layout::Msvc => 16 + 2 * word }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Data { Inline { offset: usize, len: usize }, Heap { ptr: usize, len: usize } }
+
+fn read_word(bytes: &[u8], word: usize) -> usize {
+    bytes[..word].iter().rev().fold(0, |acc, &b| (acc << 8) | b as usize)
+}
+
+/// Works out where the characters are from the raw string object, `None` if it is not a sane string.
+fn decode(layout: Layout, header: &[u8], word: usize) -> Option<Data> {
+    if header.len() < layout.size(word) { return None; }
+    let data = match layout {
+        Layout::LibCxx if header[0] & 1 == 0 => Data::Inline { offset: 1, len: (header[0] >> 1) as usize },
+        Layout::LibCxx => Data::Heap { ptr: read_word(&header[2 * word..], word), len: read_word(&header[word..], word) },
+        Layout::Msvc => {
+            let len = read_word(&header[16..], word);
+            let cap = read_word(&header[16 + word..], word);
+            if len > cap { return None; }
+            if cap < 16 { Data::Inline { offset: 0, len } } else { Data::Heap { ptr: read_word(header, word), len } }
+        }
+    };
+    match data {
+        Data::Inline { offset, len } if offset + len >= layout.size(word) => None,
+        Data::Heap { ptr, len } if ptr == 0 || len > MAX_LONG_LEN => None,
+        data => Some(data),
+    }
+}
+
+fn decode_bytes(layout: Layout, header: &[u8], word: usize, heap: impl Fn(usize, usize) -> Result<Vec<u8>, MemoryError>) -> Option<Result<Vec<u8>, MemoryError>> {
+    Some(match decode(layout, header, word)? {
+        Data::Inline { offset, len } => Ok(header[offset..offset + len].to_vec()),
+        Data::Heap { ptr, len } => heap(ptr, len),
+    })
+}
+
+/// Reads the `std::string` at `addr` with the standard library layout of the current platform,
+/// following the heap pointer for strings too long for the inline buffer.
+pub fn read(regions: &Regions, addr: usize) -> Result<String, MemoryError> {
+    const WORD: usize = std::mem::size_of::<usize>();
+    let mut header = vec![0; Layout::NATIVE.size(WORD)];
+    regions.read_bytes(addr, &mut header)?;
+    let bytes = decode_bytes(Layout::NATIVE, &header, WORD, |ptr, len| {
+        let mut buf = vec![0; len];
+        regions.read_bytes(ptr, &mut buf).map(|_| buf)
+    }).ok_or(MemoryError { addr, len: header.len(), access: "a valid std::string" })??;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEAP_PTR: usize = 0x1000_2000;
+    const LONG_NAME: &[u8] = b"my_addon:floating_islands_dimension";
+
+    fn heap(ptr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        if ptr != HEAP_PTR || len > LONG_NAME.len() { return Err(MemoryError { addr: ptr, len, access: "readable" }); }
+        Ok(LONG_NAME[..len].to_vec())
+    }
+
+    fn fixture(parts: &[&[u8]], size: usize) -> Vec<u8> {
+        let mut bytes = parts.concat();
+        bytes.resize(size, 0);
+        bytes
+    }
+
+    fn word(value: usize, word: usize) -> Vec<u8> { value.to_le_bytes()[..word].to_vec() }
+
+    fn decoded(layout: Layout, header: &[u8], word: usize) -> Option<Vec<u8>> {
+        decode_bytes(layout, header, word, heap).and_then(Result::ok)
+    }
+
+    #[test]
+    fn libcxx_short() {
+        for w in [4, 8] {
+            let header = fixture(&[&[9 << 1], b"Overworld\0"], Layout::LibCxx.size(w));
+            assert_eq!(decoded(Layout::LibCxx, &header, w).as_deref(), Some(&b"Overworld"[..]));
+        }
+    }
+
+    #[test]
+    fn libcxx_short_longest_inline() {
+        let name = b"abcdefghijklmnopqrstuv";
+        let header = fixture(&[&[(name.len() as u8) << 1], name, b"\0"], Layout::LibCxx.size(8));
+        assert_eq!(decoded(Layout::LibCxx, &header, 8).as_deref(), Some(&name[..]));
+    }
+
+    #[test]
+    fn libcxx_long() {
+        for w in [4, 8] {
+            let header = fixture(&[&word(48 | 1, w), &word(LONG_NAME.len(), w), &word(HEAP_PTR, w)], Layout::LibCxx.size(w));
+            assert_eq!(decoded(Layout::LibCxx, &header, w).as_deref(), Some(LONG_NAME));
+        }
+    }
+
+    #[test]
+    fn libcxx_rejects_garbage() {
+        let oversized_short = fixture(&[&[30 << 1]], Layout::LibCxx.size(8));
+        assert_eq!(decode(Layout::LibCxx, &oversized_short, 8), None);
+        let null_heap = fixture(&[&word(48 | 1, 8), &word(20, 8), &word(0, 8)], Layout::LibCxx.size(8));
+        assert_eq!(decode(Layout::LibCxx, &null_heap, 8), None);
+        let huge = fixture(&[&word(4097, 8), &word(4000, 8), &word(HEAP_PTR, 8)], Layout::LibCxx.size(8));
+        assert_eq!(decode(Layout::LibCxx, &huge, 8), None);
+    }
+
+    #[test]
+    fn msvc_short() {
+        for w in [4, 8] {
+            let header = fixture(&[&fixture(&[b"Nether\0"], 16), &word(6, w), &word(15, w)], Layout::Msvc.size(w));
+            assert_eq!(decoded(Layout::Msvc, &header, w).as_deref(), Some(&b"Nether"[..]));
+        }
+    }
+
+    #[test]
+    fn msvc_long() {
+        for w in [4, 8] {
+            let header = fixture(&[&fixture(&[&word(HEAP_PTR, w)], 16), &word(LONG_NAME.len(), w), &word(47, w)], Layout::Msvc.size(w));
+            assert_eq!(decoded(Layout::Msvc, &header, w).as_deref(), Some(LONG_NAME));
+        }
+    }
+
+    #[test]
+    fn msvc_rejects_garbage() {
+        let size_over_cap = fixture(&[&fixture(&[b"TheEnd\0"], 16), &word(20, 8), &word(15, 8)], Layout::Msvc.size(8));
+        assert_eq!(decode(Layout::Msvc, &size_over_cap, 8), None);
+        let short_buffer = vec![0; Layout::Msvc.size(8) - 1];
+        assert_eq!(decode(Layout::Msvc, &short_buffer, 8), None);
+    }
+
+    #[test]
+    fn heap_read_errors_are_reported() {
+        let header = fixture(&[&word(48 | 1, 8), &word(LONG_NAME.len(), 8), &word(HEAP_PTR + 8, 8)], Layout::LibCxx.size(8));
+        assert!(matches!(decode_bytes(Layout::LibCxx, &header, 8, heap), Some(Err(_))));
+    }
+}
@@ -1,4 +1,4 @@
-use std::{fs::{self, File}, io::ErrorKind, path::Path, error::Error};
+use std::{fs::{self, File}, io::ErrorKind, path::Path, error::Error, sync::OnceLock, time::{Duration, Instant}};
 
 pub fn combine_hex(max: i16, min: i16) -> i32 { 
     ((max as i32) << 16) | (min as u16 as i32)
@@ -37,12 +37,52 @@ pub fn find_max_less_than(data: &[usize], target: usize) -> Option<usize> {
     if low == 0 { None } else { Some(unsafe { *data.get_unchecked(low - 1) }) }
 }
 
+/// Matches `text` against a pattern where `*` is any run of bytes and `?` is exactly one.
+pub fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => { backtrack = Some((p, t)); p += 1; }
+            Some(&c) if c == b'?' || c == text[t] => { p += 1; t += 1; }
+            _ => match backtrack {
+                Some((star, mark)) => { p = star + 1; t = mark + 1; backtrack = Some((star, mark + 1)); }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
 pub struct TextMapRange { pub start: usize, pub size: usize }
 
+static GAME_VERSION: OnceLock<String> = OnceLock::new();
+
+pub fn game_version() -> Option<&'static str> { GAME_VERSION.get().map(String::as_str) }
+
+#[cfg(any(target_os = "android", target_os = "windows"))]
+pub fn set_game_version(version: String) {
+    log::info!("Game version: {}", version);
+    let _ = GAME_VERSION.set(version);
+}
+
+#[cfg(target_os = "windows")]
+pub fn detect_game_version() -> Option<String> {
+    let v = windows::ApplicationModel::Package::Current().ok()?.Id().ok()?.Version().ok()?;
+    Some(format!("{}.{}.{}.{}", v.Major, v.Minor, v.Build, v.Revision))
+}
+
+#[cfg(target_os = "android")]
+pub fn detect_game_version(env: &mut jni::JNIEnv) -> Option<String> {
+    let context = get_global_context(env)?;
+    get_version_name(env, context.as_obj())
+}
+
+/// The platform's places for the config folder, best first, each paired with where it came from.
 #[cfg_attr(target_os = "android", no_mangle)]
-pub fn get_config_directory(#[cfg(target_os = "android")] env: &mut jni::JNIEnv) -> Option<String> {
+pub fn get_config_directories(#[cfg(target_os = "android")] env: &mut jni::JNIEnv) -> Vec<(String, String)> {
     #[cfg(target_os = "linux")]
-    { std::env::current_exe().ok().and_then(|path| path.parent().map(|p| p.to_string_lossy().to_string())) }
+    { std::env::current_exe().ok().and_then(|path| path.parent().map(|p| p.to_string_lossy().to_string())).map(|d| (d, String::from("platform default"))).into_iter().collect() }
     #[cfg(target_os = "windows")] { 
         windows::ApplicationModel::Package::Current().ok()
             .and_then(|_| { 
@@ -51,24 +91,143 @@ pub fn get_config_directory(#[cfg(target_os = "android")] env: &mut jni::JNIEnv)
             .map(|p| p.to_string_lossy().to_owned())
             .or_else(|| {
                 std::env::current_exe().ok()?.parent()?.to_str().map(String::from) 
-            }) 
+            })
+            .map(|d| (d, String::from("platform default"))).into_iter().collect()
     }
     
-    #[cfg(target_os = "android")]
-    { get_global_context(env).and_then(|ctx| {get_games_directory(env).or_else(|| get_app_external_files_dir(env, ctx.as_obj()))}) }
+    // /sdcard/games needs the storage permission, the app's own folders never do
+    #[cfg(target_os = "android")] {
+        let mut dirs: Vec<(String, String)> = get_games_directory(env).map(|d| (d, String::from("games directory"))).into_iter().collect();
+        if let Some(ctx) = get_global_context(env) {
+            dirs.extend(get_app_external_files_dir(env, ctx.as_obj()).map(|d| (d, String::from("app external files directory"))));
+            dirs.extend(get_app_files_dir(env, ctx.as_obj()).map(|d| (d, String::from("app files directory"))));
+        }
+        dirs
+    }
+}
+
+const APP_DIR: &str = "BuildLimitChanger";
+
+/// `$<var>/BuildLimitChanger`, else `$HOME/<home_fallback>/BuildLimitChanger` as the XDG spec defaults.
+#[cfg(target_os = "linux")]
+fn xdg_dir(var: &str, home_fallback: &str) -> Option<(String, String)> {
+    match std::env::var(var).ok().filter(|d| Path::new(d).is_absolute()) {
+        Some(dir) => Some((format!("{dir}/{APP_DIR}/"), var.to_string())),
+        None => std::env::var("HOME").ok().map(|home| (format!("{home}/{home_fallback}/{APP_DIR}/"), format!("~/{home_fallback}"))),
+    }
+}
+
+#[cfg(not(target_os = "android"))]
+fn temp_dir() -> (String, String) {
+    (std::env::temp_dir().join(APP_DIR).to_string_lossy().into_owned(), String::from("temp directory"))
+}
+
+/// Apps cannot write to `/data/local/tmp`, the app's cache directory is the last resort instead.
+#[cfg(target_os = "android")]
+fn temp_dir() -> (String, String) {
+    // an app's process name is its package name
+    let package = fs::read("/proc/self/cmdline").ok()
+        .and_then(|cmdline| String::from_utf8(cmdline.split(|&b| b == 0 || b == b':').next()?.to_vec()).ok())
+        .unwrap_or_else(|| String::from("com.mojang.minecraftpe"));
+    (format!("/data/data/{package}/cache/{APP_DIR}/"), String::from("app cache directory"))
+}
+
+/// Directories to keep the config in, best first, each paired with where it came from.
+/// `platform_dirs` are parents that get a `BuildLimitChanger` folder.
+pub fn config_dir_candidates(platform_dirs: Vec<(String, String)>) -> Vec<(String, String)> {
+    let mut dirs = Vec::new();
+    if let Some(o) = crate::config::override_value("CONFIG_DIR") { dirs.push((o.value.clone(), o.source.clone())); }
+    dirs.extend(platform_dirs.into_iter().map(|(dir, source)| (format!("{dir}/{APP_DIR}/"), source)));
+    #[cfg(target_os = "linux")]
+    dirs.extend(xdg_dir("XDG_CONFIG_HOME", ".config"));
+    dirs.push(temp_dir());
+    dirs
+}
+
+/// Directories to write `log.txt` to, best first. Logs go next to the config unless overridden.
+pub fn log_dir_candidates(config_dir: Option<&str>) -> Vec<(String, String)> {
+    let mut dirs = Vec::new();
+    if let Some(o) = crate::config::override_value("LOG_DIR") { dirs.push((o.value.clone(), o.source.clone())); }
+    if let Some(dir) = config_dir { dirs.push((dir.to_string(), String::from("config directory"))); }
+    #[cfg(target_os = "linux")]
+    dirs.extend(xdg_dir("XDG_STATE_HOME", ".local/state"));
+    dirs.push(temp_dir());
+    dirs
+}
+
+pub const MAIN_EXECUTABLE: &str = "main";
+
+/// Resolves the module to scan from `BLC_MODULE` / `--blc-module`, then the config, then the
+/// platform default. `None` means the main executable.
+pub fn target_module() -> Option<String> {
+    let (source, configured) = match crate::config::override_value("MODULE") {
+        Some(o) => (o.source.as_str(), Some(o.value.clone())),
+        None => ("config", crate::config::load().settings.module),
+    };
+    if let Some(m) = &configured { log::info!("Target module from {}: {}", source, m); }
+    match configured.as_deref() {
+        Some(MAIN_EXECUTABLE) => None,
+        Some(m) => Some(m.to_string()),
+        #[cfg(target_os = "android")]
+        None => Some(String::from("libminecraftpe.so")),
+        #[cfg(not(target_os = "android"))]
+        None => None,
+    }
+}
+
+/// Longest pause between two checks in `wait_for_module`.
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How often `wait_for_module` looks for the module by its ELF header, which touches memory.
+const HEADER_WALK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether `module` (from `target_module`) is mapped yet. Never loads it.
+pub fn module_loaded(module: Option<&str>) -> bool { module_mapped(module, true) }
+
+/// `module_loaded`, looking at the ELF headers of unnamed mappings only with `walk_headers`.
+fn module_mapped(module: Option<&str>, walk_headers: bool) -> bool {
+    let Some(module) = module else { return true };
+    #[cfg(any(target_os = "android", target_os = "linux"))] {
+        let Ok(name) = std::ffi::CString::new(module) else { return false };
+        let handle = unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_LAZY | libc::RTLD_NOLOAD) };
+        if !handle.is_null() {
+            unsafe { libc::dlclose(handle) };
+            return true;
+        }
+        // launchers loading the game from memory only show up in the maps
+        if walk_headers { crate::maps::find_text_section(module).is_ok() } else { crate::maps::is_mapped_by_path(module).unwrap_or(false) }
+    }
+    #[cfg(target_os = "windows")] {
+        let _ = walk_headers;
+        let wide: Vec<u16> = module.encode_utf16().chain(std::iter::once(0)).collect();
+        unsafe { windows_sys::Win32::System::LibraryLoader::GetModuleHandleW(wide.as_ptr()) != 0 }
+    }
+}
+
+/// Polls until `module` is mapped, for mods that get loaded before the game library.
+/// Returns false if it did not show up within `timeout`.
+pub fn wait_for_module(module: Option<&str>, timeout: Duration) -> bool {
+    let start = Instant::now();
+    let mut interval = Duration::from_millis(10);
+    let mut last_walk = start;
+    loop {
+        let walk_headers = last_walk.elapsed() >= HEADER_WALK_INTERVAL;
+        if walk_headers { last_walk = Instant::now(); }
+        if module_mapped(module, walk_headers) { return true; }
+        let left = timeout.saturating_sub(start.elapsed());
+        // a last full look before giving up
+        if left.is_zero() { return module_loaded(module); }
+        std::thread::sleep(interval.min(left));
+        interval = (interval * 2).min(MAX_POLL_INTERVAL);
+    }
 }
 
 #[cfg(any(target_os = "android", target_os = "linux"))]
-fn find_text_section_for_target(target: &str) -> Result<TextMapRange, Box<dyn Error>> {
+fn find_text_section_for_target(target: &str, is_executable: bool) -> Result<TextMapRange, Box<dyn Error>> {
     use libc::c_void;
     use std::ffi::CString;
     use std::path::Path;
 
-    let is_executable = {
-        let path = Path::new(target);
-        path.exists() || (!target.ends_with(".so") && !target.contains('/'))
-    };
-
     struct Ctx { handle: *mut c_void, target_name: String, is_exe: bool, range: (*mut u8, usize) }
 
     extern "C" fn callback(info: *mut libc::dl_phdr_info, _: libc::size_t, data: *mut c_void) -> libc::c_int {
@@ -123,8 +282,9 @@ fn find_text_section_for_target(target: &str) -> Result<TextMapRange, Box<dyn Er
     unsafe {
         let handle = if is_executable { std::ptr::null_mut() } else {
             let target_cstr = CString::new(target)?;
-            let h = libc::dlopen(target_cstr.as_ptr(), libc::RTLD_LAZY);
-            if h.is_null() { return Err(format!("Cannot find library: {}", target).into()); }
+            // NOLOAD: loading the game ourselves, before its loader does, would break it
+            let h = libc::dlopen(target_cstr.as_ptr(), libc::RTLD_LAZY | libc::RTLD_NOLOAD);
+            if h.is_null() { return Err(format!("Library not loaded: {}", target).into()); }
             h
         };
         
@@ -143,15 +303,30 @@ fn find_text_section_for_target(target: &str) -> Result<TextMapRange, Box<dyn Er
 }
 
 pub fn find_minecraft_text_section() -> Result<TextMapRange, Box<dyn Error>> {
-    #[cfg(target_os = "linux")] {
-        let exe = std::env::current_exe()?.to_string_lossy().into_owned();
-        find_text_section_for_target(&exe).map_err(|_| format!("Can't find executable text section for {exe}").into())
+    #[cfg(any(target_os = "android", target_os = "linux"))] {
+        use crate::{config::{self, ModuleLocator}, maps};
+        let module = target_module();
+        let is_exe = module.is_none();
+        let target = match module {
+            Some(m) => m,
+            None => std::env::current_exe()?.to_string_lossy().into_owned(),
+        };
+        match config::load().settings.module_locator {
+            ModuleLocator::Dl => find_text_section_for_target(&target, is_exe),
+            ModuleLocator::Maps => maps::find_text_section(&target),
+            ModuleLocator::Auto => find_text_section_for_target(&target, is_exe).or_else(|e| {
+                log::warn!("{e}, falling back to /proc/self/maps");
+                maps::find_text_section(&target)
+            }),
+        }.map_err(|e| format!("Can't find executable text section for {target}: {e}").into())
     }
-    #[cfg(target_os = "android")] { find_text_section_for_target("libminecraftpe.so").map_err(|_| "Can't find text section for libminecraftpe.so".into()) }
     #[cfg(target_os = "windows")] unsafe {
         use windows_sys::Win32::System::{LibraryLoader::GetModuleHandleW, ProcessStatus::{GetModuleInformation, MODULEINFO}, Threading::GetCurrentProcess};
-        let h_module = GetModuleHandleW(std::ptr::null());
-        if h_module == 0 { return Err("Failed to get module handle for main executable".into()); }
+        let module = target_module();
+        let wide_name = module.as_ref().map(|m| m.encode_utf16().chain(std::iter::once(0)).collect::<Vec<u16>>());
+        let h_module = GetModuleHandleW(wide_name.as_ref().map_or(std::ptr::null(), |w| w.as_ptr()));
+        let module = module.unwrap_or_else(|| String::from("main executable"));
+        if h_module == 0 { return Err(format!("Failed to get module handle for {module}").into()); }
 
         let mut mod_info = std::mem::zeroed::<MODULEINFO>();
         if GetModuleInformation(GetCurrentProcess(), h_module, &mut mod_info, std::mem::size_of::<MODULEINFO>() as u32) == 0 {
@@ -165,7 +340,7 @@ pub fn find_minecraft_text_section() -> Result<TextMapRange, Box<dyn Error>> {
         let text_addr = base_addr + text_section.VirtualAddress as usize;
         let text_size = text_section.VirtualSize as usize;
 
-        log::debug!("Minecraft.Windows.exe .text: addr = 0x{:x}, size = 0x{:x}", text_addr, text_size);
+        log::debug!("{} .text: addr = 0x{:x}, size = 0x{:x}", module, text_addr, text_size);
 
         Ok(TextMapRange { start: text_addr, size: text_size })
     }
@@ -200,6 +375,13 @@ mod android_specific {
         get_absolute_path_from_file(env, file_obj)
     }
 
+    pub fn get_app_files_dir(env: &mut JNIEnv, context: &JObject) -> Option<String> {
+        let file_obj = env
+            .call_method(context, "getFilesDir", "()Ljava/io/File;", &[])
+            .ok()?.l().ok()?;
+        get_absolute_path_from_file(env, file_obj)
+    }
+
     pub fn get_global_context(env: &mut JNIEnv) -> Option<GlobalRef> {
         let activity_thread_class = env.find_class("android/app/ActivityThread").ok()?;
         let at_instance = env
@@ -223,6 +405,23 @@ mod android_specific {
         env.get_string(&JString::from(abs_path)).ok().map(|s| s.into())
     }
 
+    pub fn get_version_name(env: &mut JNIEnv, context: &JObject) -> Option<String> {
+        let package_name = env.new_string(get_package_name(env, context)?).ok()?;
+        let package_manager = env
+            .call_method(context, "getPackageManager", "()Landroid/content/pm/PackageManager;", &[])
+            .ok()?.l().ok()?;
+        let package_info = env
+            .call_method(package_manager, "getPackageInfo", "(Ljava/lang/String;I)Landroid/content/pm/PackageInfo;", &[(&package_name).into(), 0.into()])
+            .ok()?.l().ok()?;
+
+        if env.exception_check().unwrap_or(false) {
+            let _ = env.exception_clear();
+            return None;
+        }
+        let version = env.get_field(package_info, "versionName", "Ljava/lang/String;").ok()?.l().ok()?;
+        env.get_string(&JString::from(version)).ok().map(|s| s.into())
+    }
+
     fn get_package_name(env: &mut JNIEnv, context: &JObject) -> Option<String> {
         let jstr = env
             .call_method(context, "getPackageName", "()Ljava/lang/String;", &[])